@@ -1,7 +1,12 @@
-use std::{borrow::Cow, ops::Range};
+use std::{borrow::Cow, fmt::Display, ops::Range, str::FromStr};
+
+use termcolor::Color;
+
+use crate::{Files, FilesError, Location, Renderer, plain::Plain};
 
 /// A reference to a source code.
-#[derive(Debug, PartialEq, PartialOrd, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FileId(pub usize);
 
 impl From<usize> for FileId {
@@ -11,7 +16,12 @@ impl From<usize> for FileId {
 }
 
 /// Severity of diagnostic reporting.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+///
+/// Levels are ordered by severity: `Help < Note < Warning < Error < Bug`, so that
+/// `level >= Level::Warning` selects warnings and worse.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Level {
     /// An unexpected bug.
     Bug,
@@ -24,17 +34,319 @@ pub enum Level {
     /// A help message.
     Help,
 }
+
+impl Level {
+    /// Returns the lowercase name of this level, as accepted by [`FromStr`].
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::Bug => "bug",
+            Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note => "note",
+            Level::Help => "help",
+        }
+    }
+
+    /// Returns the word that [`Term`](crate::term::Term) renders in the header, padded to a common width.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Level::Bug => "  bug",
+            Level::Error => "error",
+            Level::Warning => " warn",
+            Level::Note => " note",
+            Level::Help => " help",
+        }
+    }
+
+    /// Returns the color that [`Term`](crate::term::Term) renders the header word in.
+    pub fn default_color(&self) -> Color {
+        match self {
+            Level::Bug => Color::Magenta,
+            Level::Error => Color::Red,
+            Level::Warning => Color::Yellow,
+            Level::Note => Color::White,
+            Level::Help => Color::Ansi256(255),
+        }
+    }
+}
+
+/// The severity rank of a level, from 0 for [`Help`](Level::Help) to 4 for [`Bug`](Level::Bug).
+impl From<Level> for u8 {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Help => 0,
+            Level::Note => 1,
+            Level::Warning => 2,
+            Level::Error => 3,
+            Level::Bug => 4,
+        }
+    }
+}
+
+impl PartialOrd for Level {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Level {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        u8::from(*self).cmp(&u8::from(*other))
+    }
+}
+
+/// Error returns by [`Level::from_str`].
+#[derive(Debug, PartialEq, Eq, Clone, thiserror::Error)]
+#[error("unknown diagnostic level `{0}`, expect one of: bug, error, warning, note, help")]
+pub struct ParseLevelError(pub String);
+
+impl FromStr for Level {
+    type Err = ParseLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "bug" => Ok(Level::Bug),
+            "error" => Ok(Level::Error),
+            "warn" | "warning" => Ok(Level::Warning),
+            "note" => Ok(Level::Note),
+            "help" => Ok(Level::Help),
+            _ => Err(ParseLevelError(s.to_string())),
+        }
+    }
+}
+
+/// A code that identifies a kind of diagnostic, e.g. `308`, `E0308` or `clippy::needless_return`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(untagged))]
+pub enum Code<'a> {
+    /// A numeric code, displayed zero-padded to six digits, e.g. `000308`.
+    Number(usize),
+    /// An alphanumeric code, displayed verbatim.
+    Name(Cow<'a, str>),
+}
+
+impl Code<'_> {
+    /// Returns the code as users type it, e.g. in a url or an `explain` command:
+    /// numbers without padding and names verbatim.
+    pub fn name(&self) -> Cow<'_, str> {
+        match self {
+            Code::Number(code) => code.to_string().into(),
+            Code::Name(name) => Cow::Borrowed(name),
+        }
+    }
+
+    /// Returns a copy of this code that owns its name.
+    pub fn into_owned(self) -> Code<'static> {
+        match self {
+            Code::Number(code) => Code::Number(code),
+            Code::Name(name) => Code::Name(name.into_owned().into()),
+        }
+    }
+}
+
+impl Display for Code<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Code::Number(code) => write!(f, "{:06}", code),
+            Code::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl From<usize> for Code<'_> {
+    fn from(value: usize) -> Self {
+        Code::Number(value)
+    }
+}
+
+impl<'a> From<&'a str> for Code<'a> {
+    fn from(value: &'a str) -> Self {
+        Code::Name(value.into())
+    }
+}
+
+impl From<String> for Code<'_> {
+    fn from(value: String) -> Self {
+        Code::Name(value.into())
+    }
+}
+
+impl<'a> From<Cow<'a, str>> for Code<'a> {
+    fn from(value: Cow<'a, str>) -> Self {
+        Code::Name(value)
+    }
+}
+
+/// The stage of a multi-phase tool that reports a diagnostic.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Stage {
+    /// Lexing/parsing of the named language or format, e.g. `Stage::Parsing("SVG")`.
+    Parsing(&'static str),
+    /// Semantic analysis of the named language or format.
+    Analysis(&'static str),
+    /// Code generation for the named target.
+    Codegen(&'static str),
+    /// Any other named stage.
+    Custom(&'static str),
+}
+
+/// Displays e.g. `parsing SVG`, or the bare name of a custom stage.
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::Parsing(name) => write!(f, "parsing {}", name),
+            Stage::Analysis(name) => write!(f, "analysis {}", name),
+            Stage::Codegen(name) => write!(f, "codegen {}", name),
+            Stage::Custom(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Error returns by [`Diagnostic::validate`].
+#[derive(Debug, PartialEq, Eq, Clone, thiserror::Error)]
+pub enum ValidationError {
+    /// A label references a file that is not provided.
+    #[error("label references file id {} but no such source was provided", .0.0)]
+    MissingFile(FileId),
+    /// A region of a label starts after its end.
+    #[error("reversed range {}..{} in file id {}", .range.start, .range.end, .id.0)]
+    ReversedRange {
+        /// The file of the region.
+        id: FileId,
+        /// The byte range of the region.
+        range: Range<usize>,
+    },
+    /// A region references an offset or line that is not in its file.
+    #[error(transparent)]
+    Files(#[from] FilesError),
+}
+
+impl ValidationError {
+    /// Convert this error into a [`Bug`](Level::Bug) diagnostic, so that span bugs show up
+    /// in the same output as the real diagnostics.
+    pub fn to_diagnostic(&self) -> Diagnostic<'static> {
+        Diagnostic::bug("internal error: invalid span").with_note(self.to_string())
+    }
+}
+
 /// Region of one label.
-#[derive(Debug, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LabelRegion<'a> {
     /// The region of code associated with a diagnostic.
     pub range: Range<usize>,
     /// describing of the region.
     pub message: Cow<'a, str>,
+    /// The region given as line/column pairs, if any.
+    ///
+    /// When set, it takes precedence over `range` and is converted to byte offsets
+    /// at render time via [`Files::to_offset`].
+    pub location: Option<Range<Location>>,
+    /// The whole line given by its 1-based number, if any.
+    ///
+    /// When set, it takes precedence over `range` and `location`, and is converted to the byte
+    /// range of the line content at render time.
+    pub line: Option<usize>,
+    /// Underline glyph of this region, overriding the per-level glyph of the theme.
+    pub glyph: Option<char>,
+}
+
+impl LabelRegion<'_> {
+    /// Returns the byte range of this region in the file `id`.
+    pub fn byte_range<F>(&self, files: &F, id: FileId) -> Result<Range<usize>, FilesError>
+    where
+        F: Files,
+    {
+        if let Some(line) = self.line {
+            return files.line_range(id, line);
+        }
+
+        match &self.location {
+            Some(location) => {
+                Ok(files.to_offset(id, location.start)?..files.to_offset(id, location.end)?)
+            }
+            None => Ok(self.range.clone()),
+        }
+    }
+
+    /// The key of [`Diagnostic::sort_labels`], which does not resolve regions.
+    fn sort_key(&self) -> SortKey<'_> {
+        (
+            self.line,
+            self.location
+                .as_ref()
+                .map(|location| (location.start, location.end)),
+            self.range.start,
+            self.range.end,
+            &self.message,
+        )
+    }
+}
+
+/// A (line, location, byte range, message) key of a region.
+type SortKey<'r> = (
+    Option<usize>,
+    Option<(Location, Location)>,
+    usize,
+    usize,
+    &'r str,
+);
+
+/// How confidently a [`Suggestion`] can be applied by tools, as in rustc.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Applicability {
+    /// The suggestion is definitely what the user intended, and can be applied automatically.
+    MachineApplicable,
+    /// The suggestion may be what the user intended, but it is uncertain.
+    MaybeIncorrect,
+    /// The suggestion contains placeholders like `(...)`, and can not be applied as is.
+    HasPlaceholders,
+    /// The applicability is unknown.
+    #[default]
+    Unspecified,
+}
+
+impl Applicability {
+    /// Returns the name of this applicability in rustc's JSON diagnostics.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Applicability::MachineApplicable => "MachineApplicable",
+            Applicability::MaybeIncorrect => "MaybeIncorrect",
+            Applicability::HasPlaceholders => "HasPlaceholders",
+            Applicability::Unspecified => "Unspecified",
+        }
+    }
+}
+
+/// A suggested replacement of a region of code.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Suggestion<'a> {
+    /// The region of code to replace.
+    pub range: Range<usize>,
+    /// The replacement text.
+    pub replacement: Cow<'a, str>,
+    /// How confidently the replacement can be applied.
+    pub applicability: Applicability,
+}
+
+/// Visual priority of the main region of a [`Label`].
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LabelStyle {
+    /// The main region is the cause of the diagnostic.
+    #[default]
+    Primary,
+    /// The main region only adds context, and is styled like a secondary region.
+    Secondary,
 }
 
 /// A label describing an underlined region of code associated with a diagnostic.
-#[derive(Debug, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Label<'a> {
     /// The file that associated with diagnostic reporting.
     pub id: FileId,
@@ -42,6 +354,12 @@ pub struct Label<'a> {
     pub primary: LabelRegion<'a>,
     /// Secondary regions.
     pub secondary: Vec<LabelRegion<'a>>,
+    /// Suggested fixes in the same file.
+    pub suggestions: Vec<Suggestion<'a>>,
+    /// Visual priority of the `primary` region.
+    pub style: LabelStyle,
+    /// Severity of this label, if it differs from the severity of its diagnostic.
+    pub level: Option<Level>,
 }
 
 impl<'a> Label<'a> {
@@ -57,11 +375,64 @@ impl<'a> Label<'a> {
             primary: LabelRegion {
                 range: range.into(),
                 message: message.into(),
+                location: None,
+                line: None,
+                glyph: None,
             },
             secondary: vec![],
+            suggestions: vec![],
+            style: LabelStyle::Primary,
+            level: None,
         }
     }
 
+    /// Create a new primary-styled `Label`, same as [`new`](Self::new).
+    pub fn primary<ID, R, M>(id: ID, range: R, message: M) -> Self
+    where
+        FileId: From<ID>,
+        Range<usize>: From<R>,
+        Cow<'a, str>: From<M>,
+    {
+        Self::new(id, range, message)
+    }
+
+    /// Create a new `Label` whose main region is styled like a secondary region,
+    /// to add context without marking the cause of the diagnostic.
+    pub fn secondary<ID, R, M>(id: ID, range: R, message: M) -> Self
+    where
+        FileId: From<ID>,
+        Range<usize>: From<R>,
+        Cow<'a, str>: From<M>,
+    {
+        let mut label = Self::new(id, range, message);
+        label.style = LabelStyle::Secondary;
+        label
+    }
+
+    /// Create a new `Label` whose primary region is given as line/column pairs,
+    /// for frontends that do not track byte offsets.
+    pub fn from_location<ID, M>(id: ID, start: Location, end: Location, message: M) -> Self
+    where
+        FileId: From<ID>,
+        Cow<'a, str>: From<M>,
+    {
+        let mut label = Self::new(id, 0..0, message);
+        label.primary.location = Some(start..end);
+        label
+    }
+
+    /// Create a new `Label` whose primary region is the whole content of line `line`
+    /// (1-based), for frontends that only know the line of a problem.
+    pub fn line<ID, M>(id: ID, line: usize, message: M) -> Self
+    where
+        FileId: From<ID>,
+        Cow<'a, str>: From<M>,
+    {
+        let mut label = Self::new(id, 0..0, message);
+        label.primary.line = Some(line);
+        label
+    }
+
     /// Append a new secondary describing region.
     pub fn with_secondary<R, M>(mut self, range: R, message: M) -> Self
     where
@@ -71,25 +442,124 @@ impl<'a> Label<'a> {
         self.secondary.push(LabelRegion {
             range: range.into(),
             message: message.into(),
+            location: None,
+            line: None,
+            glyph: None,
+        });
+
+        self
+    }
+
+    /// Append a new secondary describing region, underlined with `glyph`.
+    pub fn with_secondary_glyph<R, M>(self, range: R, message: M, glyph: char) -> Self
+    where
+        Range<usize>: From<R>,
+        Cow<'a, str>: From<M>,
+    {
+        let mut label = self.with_secondary(range, message);
+
+        if let Some(region) = label.secondary.last_mut() {
+            region.glyph = Some(glyph);
+        }
+
+        label
+    }
+
+    /// Set the severity of this label, when it differs from the severity of its diagnostic,
+    /// e.g. for diagnostics assembled from heterogeneous sources.
+    pub fn with_level(mut self, level: Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Underline the primary region with `glyph`, instead of the glyph of the theme.
+    pub fn with_glyph(mut self, glyph: char) -> Self {
+        self.primary.glyph = Some(glyph);
+        self
+    }
+
+    /// Shift every byte range of this label, including suggestions, by `delta` bytes.
+    ///
+    /// Bounds saturate at `0`. This is useful to map diagnostics computed against a transformed
+    /// source (e.g. with an injected prelude) back to the original byte offsets.
+    ///
+    /// Regions given by [`location`](LabelRegion::location) or [`line`](LabelRegion::line) are
+    /// left as they are, a byte delta can not be mapped to lines and columns without the source.
+    pub fn shift(&mut self, delta: isize) {
+        shift_range(&mut self.primary.range, delta);
+
+        for region in &mut self.secondary {
+            shift_range(&mut region.range, delta);
+        }
+
+        for suggestion in &mut self.suggestions {
+            shift_range(&mut suggestion.range, delta);
+        }
+    }
+
+    /// Append a suggestion that replaces `range` with `replacement`, with an
+    /// [`Unspecified`](Applicability::Unspecified) applicability.
+    pub fn with_suggestion<R, M>(self, range: R, replacement: M) -> Self
+    where
+        Range<usize>: From<R>,
+        Cow<'a, str>: From<M>,
+    {
+        self.with_applicable_suggestion(range, replacement, Applicability::Unspecified)
+    }
+
+    /// Append a suggestion that replaces `range` with `replacement`, that tools may apply
+    /// according to `applicability`.
+    pub fn with_applicable_suggestion<R, M>(
+        mut self,
+        range: R,
+        replacement: M,
+        applicability: Applicability,
+    ) -> Self
+    where
+        Range<usize>: From<R>,
+        Cow<'a, str>: From<M>,
+    {
+        self.suggestions.push(Suggestion {
+            range: range.into(),
+            replacement: replacement.into(),
+            applicability,
         });
 
         self
     }
 }
 
+fn shift_range(range: &mut Range<usize>, delta: isize) {
+    range.start = range.start.saturating_add_signed(delta);
+    range.end = range.end.saturating_add_signed(delta);
+}
+
 /// A diagnostic reporting instance.
-#[derive(Debug, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Diagnostic<'a> {
     /// Severity of this diagnostic reporting.
     pub level: Level,
     /// An optional code the identifies this diagnostic.
-    pub code: Option<usize>,
+    pub code: Option<Code<'a>>,
     /// The main message associated with this diagnostic.
     pub message: Cow<'a, str>,
     /// Notes that are associated with the primary cause of the diagnostic.
     pub nodes: Vec<Cow<'a, str>>,
-
+    /// Help lines that suggest how to resolve the diagnostic, rendered after the notes.
+    pub helps: Vec<Cow<'a, str>>,
+    /// Labels that describe the regions of code associated with this diagnostic.
     pub labels: Vec<Label<'a>>,
+    /// Extra structured key/value data for machine consumers, rendered by the terminal
+    /// renderer only in [`Verbose`](crate::term::Verbosity::Verbose) mode.
+    pub metadata: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    /// The stage that reports this diagnostic, if any.
+    ///
+    /// It is serialized but not deserialized, as stage names are `'static`.
+    #[cfg_attr(feature = "serde", serde(skip_deserializing))]
+    pub stage: Option<Stage>,
+    /// A link to the explanation of the code, shown in the footer.
+    pub url: Option<Cow<'a, str>>,
 }
 
 impl<'a> Diagnostic<'a> {
@@ -103,7 +573,11 @@ impl<'a> Diagnostic<'a> {
             code: None,
             message: message.into(),
             nodes: vec![],
+            helps: vec![],
             labels: vec![],
+            metadata: vec![],
+            stage: None,
+            url: None,
         }
     }
     /// Create a new diagnostic with a severity of [`Bug`](Level::Bug)
@@ -144,12 +618,63 @@ impl<'a> Diagnostic<'a> {
         Self::new(Level::Help, message)
     }
 
-    /// Set optional code.
-    pub fn with_code(mut self, code: usize) -> Self {
+    /// Create a new diagnostic with a single primary label at `range` of `file`,
+    /// that repeats `message` under the source.
+    pub fn at<ID, R, M>(level: Level, file: ID, range: R, message: M) -> Self
+    where
+        FileId: From<ID>,
+        Range<usize>: From<R>,
+        Cow<'a, str>: From<M>,
+    {
+        let message = Cow::from(message);
+
+        let label = Label::new::<FileId, Range<usize>, Cow<'a, str>>(
+            file.into(),
+            range.into(),
+            message.clone(),
+        );
+
+        Self::new::<Cow<'a, str>>(level, message).with_label(label)
+    }
+
+    /// Set optional code, either numeric like `308` or alphanumeric like `"E0308"`.
+    pub fn with_code<C>(mut self, code: C) -> Self
+    where
+        Code<'a>: From<C>,
+    {
+        self.code = Some(code.into());
+        self
+    }
+
+    /// Set the code and a link to its explanation, `{base_url}/{code}`,
+    /// for crates that maintain an error index.
+    pub fn with_explained_code<C>(mut self, code: C, base_url: &str) -> Self
+    where
+        Code<'a>: From<C>,
+    {
+        let code = Code::from(code);
+
+        self.url = Some(format!("{}/{}", base_url.trim_end_matches('/'), code.name()).into());
         self.code = Some(code);
         self
     }
 
+    /// Set the link to the explanation of the code, that the terminal renderer attaches to
+    /// the code as a hyperlink where supported.
+    pub fn with_code_url<U>(mut self, url: U) -> Self
+    where
+        Cow<'a, str>: From<U>,
+    {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Set the stage that reports this diagnostic.
+    pub fn with_stage(mut self, stage: Stage) -> Self {
+        self.stage = Some(stage);
+        self
+    }
+
     /// Add some notes to the diagnostic.
     pub fn with_note<M>(mut self, message: M) -> Self
     where
@@ -159,6 +684,15 @@ impl<'a> Diagnostic<'a> {
         self
     }
 
+    /// Add a help line to the diagnostic.
+    pub fn with_help<M>(mut self, message: M) -> Self
+    where
+        Cow<'a, str>: From<M>,
+    {
+        self.helps.push(message.into());
+        self
+    }
+
     /// Add some labels to the diagnostic.
     pub fn with_label<L>(mut self, label: L) -> Self
     where
@@ -167,4 +701,643 @@ impl<'a> Diagnostic<'a> {
         self.labels.push(label.into());
         self
     }
+
+    /// Append all `notes` to the notes of the diagnostic.
+    pub fn with_notes<I, M>(mut self, notes: I) -> Self
+    where
+        I: IntoIterator<Item = M>,
+        Cow<'a, str>: From<M>,
+    {
+        self.nodes.extend(notes.into_iter().map(Cow::from));
+        self
+    }
+
+    /// Append all `labels` to the labels of the diagnostic.
+    pub fn with_labels<I, L>(mut self, labels: I) -> Self
+    where
+        I: IntoIterator<Item = L>,
+        Label<'a>: From<L>,
+    {
+        self.labels.extend(labels.into_iter().map(Label::from));
+        self
+    }
+
+    /// Sort labels by (file id, primary region), and the secondary regions and suggestions of
+    /// each label by range, so that diagnostics differing only in insertion order compare equal.
+    ///
+    /// Regions are compared by (line, location, byte range), as given: byte range regions sort
+    /// before line/column regions, which sort before whole-line regions.
+    pub fn sort_labels(&mut self) {
+        for label in &mut self.labels {
+            label
+                .secondary
+                .sort_by(|lhs, rhs| lhs.sort_key().cmp(&rhs.sort_key()));
+
+            label.suggestions.sort_by(|lhs, rhs| {
+                (lhs.range.start, lhs.range.end, &lhs.replacement).cmp(&(
+                    rhs.range.start,
+                    rhs.range.end,
+                    &rhs.replacement,
+                ))
+            });
+        }
+
+        self.labels.sort_by(|lhs, rhs| {
+            (lhs.id, lhs.primary.sort_key()).cmp(&(rhs.id, rhs.primary.sort_key()))
+        });
+    }
+
+    /// Check that every label references a file of `files`, and that every region is in range
+    /// and not reversed.
+    pub fn validate<F>(&self, files: &F) -> Result<(), ValidationError>
+    where
+        F: Files,
+    {
+        for label in &self.labels {
+            if !files.contains(label.id) {
+                return Err(ValidationError::MissingFile(label.id));
+            }
+
+            let ranges = std::iter::once(label.primary.byte_range(files, label.id))
+                .chain(
+                    label
+                        .secondary
+                        .iter()
+                        .map(|region| region.byte_range(files, label.id)),
+                )
+                .chain(
+                    label
+                        .suggestions
+                        .iter()
+                        .map(|suggestion| Ok(suggestion.range.clone())),
+                );
+
+            for range in ranges {
+                let range = range?;
+
+                if range.start > range.end {
+                    return Err(ValidationError::ReversedRange {
+                        id: label.id,
+                        range,
+                    });
+                }
+
+                files.to_location(label.id, &range)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the most severe level set on the labels, or `None` if no label sets one.
+    ///
+    /// The explicitly set `level` of the diagnostic is not taken into account, so a renderer
+    /// can compare both and escalate the header when a label is more severe.
+    pub fn max_label_level(&self) -> Option<Level> {
+        self.labels.iter().filter_map(|label| label.level).max()
+    }
+
+    /// Returns true if this diagnostic has any labels.
+    pub fn has_labels(&self) -> bool {
+        !self.labels.is_empty()
+    }
+
+    /// Returns true if this diagnostic has any notes.
+    pub fn has_notes(&self) -> bool {
+        !self.nodes.is_empty()
+    }
+
+    /// Returns true if this diagnostic has neither labels, notes nor help lines, only a header.
+    pub fn is_empty(&self) -> bool {
+        !self.has_labels() && !self.has_notes() && self.helps.is_empty()
+    }
+
+    /// Shift the byte ranges of all labels by `delta` bytes, see [`Label::shift`].
+    pub fn shift(&mut self, delta: isize) {
+        for label in &mut self.labels {
+            label.shift(delta);
+        }
+    }
+
+    /// Attach a key/value pair of metadata to the diagnostic.
+    ///
+    /// Metadata is for machine consumers: the JSON and SARIF renderers emit it, the terminal
+    /// renderer shows it only in [`Verbose`](crate::term::Verbosity::Verbose) mode.
+    pub fn with_metadata<K, V>(mut self, key: K, value: V) -> Self
+    where
+        Cow<'a, str>: From<K> + From<V>,
+    {
+        self.metadata.push((key.into(), value.into()));
+        self
+    }
+
+    /// Render this diagnostic, including the source snippets, as a color-free string.
+    ///
+    /// This is handy for logging, e.g. `log::error!("{}", diagnostic.to_plain_string(&files)?)`.
+    ///
+    /// Returns the error of `files` if a label references a file, an offset or a line that is
+    /// not in `files`, and [`FilesError::Render`] for any other rendering error.
+    pub fn to_plain_string<F>(&self, files: &F) -> Result<String, FilesError>
+    where
+        F: Files,
+    {
+        let mut plain = Plain::with_writer(vec![]);
+
+        if let Err(err) = plain.render_ref(files, self) {
+            let kind = err.kind();
+
+            return Err(
+                match err.into_inner().map(|err| err.downcast::<FilesError>()) {
+                    Some(Ok(err)) => *err,
+                    Some(Err(err)) => FilesError::Render {
+                        kind,
+                        message: err.to_string(),
+                    },
+                    None => FilesError::Render {
+                        kind,
+                        message: kind.to_string(),
+                    },
+                },
+            );
+        }
+
+        Ok(String::from_utf8(plain.into_inner()).expect("rendered output is utf8"))
+    }
+}
+
+/// A compact, single line and color-free form without source snippets, e.g. for log lines:
+/// `error[000308]: mismatched types (expected `u8`, found `()`)`.
+impl Display for Diagnostic<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.level.as_str())?;
+
+        if let Some(code) = &self.code {
+            write!(f, "[{}]", code)?;
+        }
+
+        write!(f, ": {}", self.message)?;
+
+        let mut messages = self
+            .labels
+            .iter()
+            .map(|label| &label.primary.message)
+            .filter(|message| !message.is_empty());
+
+        if let Some(first) = messages.next() {
+            write!(f, " ({}", first)?;
+
+            for message in messages {
+                write!(f, ", {}", message)?;
+            }
+
+            write!(f, ")")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use termcolor::NoColor;
+
+    use crate::{
+        SourceCodes,
+        term::{Term, Verbosity},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_level_from_str() {
+        for level in [
+            Level::Bug,
+            Level::Error,
+            Level::Warning,
+            Level::Note,
+            Level::Help,
+        ] {
+            assert_eq!(level.as_str().parse::<Level>(), Ok(level));
+            assert_eq!(level.as_str().to_uppercase().parse::<Level>(), Ok(level));
+        }
+
+        assert_eq!("warn".parse::<Level>(), Ok(Level::Warning));
+        assert_eq!("Warn".parse::<Level>(), Ok(Level::Warning));
+
+        assert_eq!(
+            "fatal".parse::<Level>(),
+            Err(ParseLevelError("fatal".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_level_style() {
+        assert_eq!(Level::Error.label(), "error");
+        assert_eq!(Level::Error.default_color(), Color::Red);
+        assert_eq!(Level::Warning.label(), " warn");
+        assert_eq!(Level::Bug.label().len(), Level::Help.label().len());
+    }
+
+    #[test]
+    fn test_bulk_builders() {
+        let labels = vec![Label::new(0, 0..2, "first"), Label::new(0, 4..6, "second")];
+
+        let diagnostic = Diagnostic::error("mismatched types")
+            .with_label(Label::new(0, 8..9, "existing"))
+            .with_labels(labels)
+            .with_note("existing")
+            .with_notes(["expected `u8`", "found `()`"])
+            .with_notes(vec![String::from("owned")]);
+
+        assert_eq!(
+            diagnostic
+                .labels
+                .iter()
+                .map(|label| label.primary.message.as_ref())
+                .collect::<Vec<_>>(),
+            ["existing", "first", "second"]
+        );
+        assert_eq!(
+            diagnostic.nodes,
+            ["existing", "expected `u8`", "found `()`", "owned"]
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let diagnostic = Diagnostic::error("mismatched types")
+            .with_code(308)
+            .with_label(Label::new(0, 0..2, "expected `u8`"))
+            .with_label(Label::new(1, 4..6, ""))
+            .with_label(Label::new(1, 8..9, "found `()`"))
+            .with_note("notes are not shown");
+
+        assert_eq!(
+            diagnostic.to_string(),
+            "error[000308]: mismatched types (expected `u8`, found `()`)"
+        );
+
+        assert_eq!(
+            Diagnostic::warning("unused variable").to_string(),
+            "warning: unused variable"
+        );
+    }
+
+    #[test]
+    fn test_level_order() {
+        let mut levels = vec![
+            Level::Warning,
+            Level::Bug,
+            Level::Help,
+            Level::Error,
+            Level::Note,
+        ];
+
+        levels.sort();
+
+        assert_eq!(
+            levels,
+            [
+                Level::Help,
+                Level::Note,
+                Level::Warning,
+                Level::Error,
+                Level::Bug
+            ]
+        );
+
+        assert!(Level::Error >= Level::Warning);
+        assert!(Level::Note < Level::Warning);
+        assert_eq!(u8::from(Level::Bug), 4);
+        assert_eq!(u8::from(Level::Help), 0);
+    }
+
+    #[test]
+    fn test_to_plain_string() {
+        let mut files = SourceCodes::default();
+
+        files.add("main.rs", "fn main() {\n    let x = 1\n}\n");
+
+        let plain = Diagnostic::error("expected `;`")
+            .with_label(Label::new(0, 16..25, "missing `;` after this statement"))
+            .with_note("statements are terminated by `;`")
+            .to_plain_string(&files)
+            .unwrap();
+
+        assert!(plain.starts_with("error: expected `;`\n"));
+        assert!(plain.contains("2 │      let x = 1\n"));
+        assert!(plain.contains("= note: statements are terminated by `;`"));
+        assert!(!plain.contains('\x1b'));
+
+        assert_eq!(
+            Diagnostic::error("out of range")
+                .with_label(Label::new(0, 0..100, "here"))
+                .to_plain_string(&files),
+            Err(FilesError::OffsetOutOfRange {
+                id: FileId(0),
+                offset: 100
+            })
+        );
+        assert_eq!(
+            Diagnostic::error("unknown file")
+                .with_label(Label::new(1, 0..1, "here"))
+                .to_plain_string(&files),
+            Err(FilesError::Render {
+                kind: std::io::ErrorKind::InvalidInput,
+                message: "diagnostic references file id 1 but no such source was provided".into()
+            })
+        );
+    }
+
+    #[test]
+    fn test_explained_code() {
+        let files = SourceCodes::default();
+
+        let diagnostic = Diagnostic::error("mismatched types")
+            .with_explained_code(308, "https://example.com/errors/");
+
+        assert_eq!(diagnostic.code, Some(Code::Number(308)));
+        assert_eq!(
+            diagnostic.url.as_deref(),
+            Some("https://example.com/errors/308")
+        );
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_verbosity(Verbosity::Verbose);
+
+        term.render(&files, diagnostic).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(output.starts_with("error[000308]: mismatched types\n"));
+        assert!(output.ends_with(" = for more information, see https://example.com/errors/308\n"));
+    }
+
+    #[test]
+    fn test_max_label_level() {
+        let diagnostic = Diagnostic::warning("suspicious cast")
+            .with_label(Label::new(0, 4..7, "truncated here").with_level(Level::Note))
+            .with_label(Label::new(0, 10..12, "overflows").with_level(Level::Error))
+            .with_label(Label::new(0, 14..16, "cast here"));
+
+        assert_eq!(diagnostic.level, Level::Warning);
+        assert_eq!(diagnostic.max_label_level(), Some(Level::Error));
+
+        assert_eq!(
+            Diagnostic::warning("suspicious cast")
+                .with_label(Label::new(0, 14..16, "cast here"))
+                .max_label_level(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_metadata() {
+        let mut files = SourceCodes::default();
+
+        files.add("main.rs", "// entry\nfn main() {}\n");
+
+        let diagnostic = Diagnostic::warning("empty function")
+            .with_label(Label::new(0, 9..21, "this function"))
+            .with_metadata("category", "style")
+            .with_metadata("pass", "lint");
+
+        assert_eq!(diagnostic.metadata.len(), 2);
+        assert_eq!(diagnostic.metadata[0], ("category".into(), "style".into()));
+
+        let plain = diagnostic.to_plain_string(&files).unwrap();
+
+        assert!(!plain.contains("category"));
+        assert!(!plain.contains("style"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_metadata_json() {
+        let mut files = SourceCodes::default();
+
+        files.add("main.rs", "// entry\nfn main() {}\n");
+
+        let diagnostic = || {
+            Diagnostic::warning("empty function")
+                .with_label(Label::new(0, 9..21, "this function"))
+                .with_metadata("category", "style")
+        };
+
+        let mut json = crate::json::Json::with_writer(vec![]);
+
+        json.render(&files, diagnostic()).unwrap();
+
+        let output = String::from_utf8(json.into_inner()).unwrap();
+        let record = serde_json::from_str::<crate::json::DiagnosticRecord>(&output).unwrap();
+
+        assert_eq!(record.metadata, diagnostic().metadata);
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        term.render(&files, diagnostic()).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(!output.contains("category"));
+        assert!(!output.contains("style"));
+    }
+
+    #[test]
+    fn test_shift() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("main.rs", "// prelude\nfn main() {}\n");
+
+        let mut label = Label::new(id, 6..10, "function name").with_secondary(2..4, "keyword");
+
+        label.shift(5);
+
+        assert_eq!(label.primary.range, 11..15);
+        assert_eq!(label.secondary[0].range, 7..9);
+
+        let location = files.to_location(id, &label.primary.range).unwrap();
+
+        assert_eq!(location.start.lines, 2);
+        assert_eq!(location.start.cols, 1);
+
+        label.shift(-10);
+
+        assert_eq!(label.primary.range, 1..5);
+        assert_eq!(label.secondary[0].range, 0..0);
+
+        let start = Location { lines: 2, cols: 4 };
+        let end = Location { lines: 2, cols: 8 };
+
+        let mut label = Label::from_location(id, start, end, "function name");
+        let mut line = Label::line(id, 2, "function");
+
+        label.shift(5);
+        line.shift(5);
+
+        // line/column regions are not shifted.
+        assert_eq!(label.primary.location, Some(start..end));
+        assert_eq!(label.primary.byte_range(&files, id), Ok(14..18));
+        assert_eq!(line.primary.line, Some(2));
+        assert_eq!(line.primary.byte_range(&files, id), Ok(11..23));
+    }
+
+    #[test]
+    fn test_sort_labels() {
+        let mut lhs = Diagnostic::error("mismatched types")
+            .with_label(Label::new(1, 4..8, "in b"))
+            .with_label(
+                Label::new(0, 10..12, "expected")
+                    .with_secondary(20..24, "found")
+                    .with_secondary(0..3, "declared"),
+            );
+
+        let mut rhs = Diagnostic::error("mismatched types")
+            .with_label(
+                Label::new(0, 10..12, "expected")
+                    .with_secondary(0..3, "declared")
+                    .with_secondary(20..24, "found"),
+            )
+            .with_label(Label::new(1, 4..8, "in b"));
+
+        assert_ne!(lhs, rhs);
+
+        lhs.sort_labels();
+        rhs.sort_labels();
+
+        assert_eq!(lhs, rhs);
+        assert_eq!(lhs.labels[0].id, FileId(0));
+        assert_eq!(lhs.labels[0].secondary[0].range, 0..3);
+
+        let start = |cols| Location { lines: 2, cols };
+
+        let mut lhs = Diagnostic::error("mismatched types")
+            .with_label(Label::line(0, 3, "in this line"))
+            .with_label(Label::from_location(0, start(5), start(9), "second"))
+            .with_label(Label::from_location(0, start(1), start(4), "first"))
+            .with_label(Label::line(0, 1, "in the first line"))
+            .with_label(Label::new(0, 4..8, "by range"));
+
+        let mut rhs = Diagnostic::error("mismatched types")
+            .with_label(Label::new(0, 4..8, "by range"))
+            .with_label(Label::line(0, 1, "in the first line"))
+            .with_label(Label::from_location(0, start(1), start(4), "first"))
+            .with_label(Label::line(0, 3, "in this line"))
+            .with_label(Label::from_location(0, start(5), start(9), "second"));
+
+        lhs.sort_labels();
+        rhs.sort_labels();
+
+        assert_eq!(lhs, rhs);
+
+        let messages = lhs
+            .labels
+            .iter()
+            .map(|label| label.primary.message.as_ref())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            messages,
+            [
+                "by range",
+                "first",
+                "second",
+                "in the first line",
+                "in this line"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_at() {
+        let diagnostic = Diagnostic::at(Level::Error, 0, 4..7, "unexpected token");
+
+        assert_eq!(diagnostic.level, Level::Error);
+        assert_eq!(diagnostic.message, "unexpected token");
+        assert_eq!(diagnostic.labels.len(), 1);
+        assert_eq!(diagnostic.labels[0].id, FileId(0));
+        assert_eq!(diagnostic.labels[0].primary.range, 4..7);
+        assert_eq!(diagnostic.labels[0].primary.message, "unexpected token");
+        assert!(diagnostic.labels[0].secondary.is_empty());
+    }
+
+    #[test]
+    fn test_label_style() {
+        let primary = Label::primary(0, 328..331, "expected `String`, found `Nat`");
+
+        assert_eq!(
+            primary,
+            Label::new(0, 328..331, "expected `String`, found `Nat`")
+        );
+        assert_eq!(primary.style, LabelStyle::Primary);
+
+        let secondary = Label::secondary(0, 258..331, "this is found to be of type `String`");
+
+        assert_eq!(secondary.style, LabelStyle::Secondary);
+        assert_eq!(secondary.primary.range, 258..331);
+        assert!(secondary.secondary.is_empty());
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn test_validate() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("main.rs", "fn main() {\n    let x = 1;\n}\n");
+
+        let valid = Diagnostic::error("unused variable").with_label(Label::new(id, 20..21, "x"));
+        assert_eq!(valid.validate(&files), Ok(()));
+
+        let missing = Diagnostic::error("unused variable").with_label(Label::new(3, 20..21, "x"));
+        assert_eq!(
+            missing.validate(&files),
+            Err(ValidationError::MissingFile(FileId(3)))
+        );
+
+        let reversed = Diagnostic::error("unused variable")
+            .with_label(Label::new(id, 20..21, "x").with_secondary(24..20, "value"));
+        let err = reversed.validate(&files).unwrap_err();
+        assert_eq!(err, ValidationError::ReversedRange { id, range: 24..20 });
+
+        let bug = err.to_diagnostic();
+        assert_eq!(bug.level, Level::Bug);
+        assert_eq!(
+            bug.to_plain_string(&files).unwrap(),
+            "  bug: internal error: invalid span\n = note: reversed range 24..20 in file id 0\n"
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let diagnostic = Diagnostic::error("mismatched types")
+            .with_code("E0308")
+            .with_label(
+                Label::new(0, 27..30, "expected `String`, found `usize`")
+                    .with_secondary(20..26, "expected due to this")
+                    .with_applicable_suggestion(
+                        27..30,
+                        "x.to_string()",
+                        Applicability::MaybeIncorrect,
+                    ),
+            )
+            .with_label(Label::secondary(1, 0..3, "defined here").with_level(Level::Note))
+            .with_note("expected type `String`")
+            .with_metadata("rule", "types");
+
+        let json = serde_json::to_string(&diagnostic).unwrap();
+
+        assert!(json.contains(r#""level":"error""#));
+        assert!(json.contains(r#""code":"E0308""#));
+
+        let parsed: Diagnostic = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed, diagnostic);
+
+        let numeric: Diagnostic = serde_json::from_str(
+            &serde_json::to_string(&Diagnostic::bug("ice").with_code(1)).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(numeric.code, Some(Code::Number(1)));
+    }
 }
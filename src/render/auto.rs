@@ -0,0 +1,131 @@
+use std::{ffi::OsString, io::IsTerminal};
+
+use super::{DynRenderer, github::GithubActions, plain::Plain, term::Term};
+
+/// The renderer kinds [`auto_renderer`] chooses between.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RendererKind {
+    /// [`GithubActions`] workflow commands.
+    GithubActions,
+    /// [`Plain`], without any escape sequence.
+    Plain,
+    /// [`Term`] with color.
+    Term,
+}
+
+/// The environment facts that drive [`auto_renderer`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RenderEnv {
+    /// Whether the process runs inside GitHub Actions (`GITHUB_ACTIONS=true`).
+    pub github_actions: bool,
+    /// Whether stdout is attached to a terminal.
+    pub is_terminal: bool,
+}
+
+impl RenderEnv {
+    /// Detect the environment of the current process.
+    pub fn detect() -> Self {
+        Self::from_vars(
+            |name| std::env::var_os(name),
+            std::io::stdout().is_terminal(),
+        )
+    }
+
+    /// Detect the environment from the variables that `var` looks up, so that the lookup can be
+    /// tested without mutating the environment of the test process.
+    fn from_vars<V>(var: V, is_terminal: bool) -> Self
+    where
+        V: Fn(&str) -> Option<OsString>,
+    {
+        Self {
+            github_actions: var("GITHUB_ACTIONS").is_some_and(|value| value == "true"),
+            is_terminal,
+        }
+    }
+
+    /// Select a renderer kind for this environment:
+    ///
+    /// | `GITHUB_ACTIONS` | stdout is a tty | renderer                         |
+    /// |------------------|-----------------|----------------------------------|
+    /// | `true`           | any             | [`RendererKind::GithubActions`]  |
+    /// | unset            | no              | [`RendererKind::Plain`]          |
+    /// | unset            | yes             | [`RendererKind::Term`]           |
+    pub fn select(&self) -> RendererKind {
+        if self.github_actions {
+            RendererKind::GithubActions
+        } else if !self.is_terminal {
+            RendererKind::Plain
+        } else {
+            RendererKind::Term
+        }
+    }
+
+    /// Create a renderer of the [`selected`](Self::select) kind that writes to stdout.
+    pub fn renderer(&self) -> Box<dyn DynRenderer> {
+        match self.select() {
+            RendererKind::GithubActions => Box::new(GithubActions::default()),
+            RendererKind::Plain => Box::new(Plain::default()),
+            RendererKind::Term => Box::new(Term::default()),
+        }
+    }
+}
+
+/// Create a renderer that does the right thing for the current process,
+/// see [`RenderEnv::select`] for the decision table.
+pub fn auto_renderer() -> Box<dyn DynRenderer> {
+    RenderEnv::detect().renderer()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        for is_terminal in [true, false] {
+            assert_eq!(
+                RenderEnv {
+                    github_actions: true,
+                    is_terminal
+                }
+                .select(),
+                RendererKind::GithubActions
+            );
+        }
+
+        assert_eq!(
+            RenderEnv {
+                github_actions: false,
+                is_terminal: false
+            }
+            .select(),
+            RendererKind::Plain
+        );
+
+        assert_eq!(
+            RenderEnv {
+                github_actions: false,
+                is_terminal: true
+            }
+            .select(),
+            RendererKind::Term
+        );
+    }
+
+    #[test]
+    fn test_from_vars() {
+        let env = |value: Option<&str>| {
+            RenderEnv::from_vars(
+                |name| {
+                    assert_eq!(name, "GITHUB_ACTIONS");
+                    value.map(OsString::from)
+                },
+                true,
+            )
+        };
+
+        assert_eq!(env(Some("true")).select(), RendererKind::GithubActions);
+        assert_eq!(env(Some("false")).select(), RendererKind::Term);
+        assert_eq!(env(None).select(), RendererKind::Term);
+    }
+}
@@ -0,0 +1,462 @@
+//! A renderer implementation that emits cargo's `--message-format=json` messages.
+
+use std::{
+    io::{Result, Stdout, Write},
+    ops::Range,
+};
+
+use serde::Serialize;
+
+use crate::{Diagnostic, Label, LabelStyle, Level};
+
+use super::{Files, FilesError, Renderer, check_files};
+
+/// The `{"reason":"compiler-message",...}` envelope of one diagnostic.
+#[derive(Debug, Serialize)]
+pub struct CompilerMessage<'r> {
+    /// Always `compiler-message`.
+    pub reason: &'static str,
+    /// The package that emits this message, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package_id: Option<&'r str>,
+    /// The diagnostic, shaped like rustc's diagnostic JSON.
+    pub message: Message<'r>,
+}
+
+/// A rustc-style diagnostic.
+#[derive(Debug, Serialize)]
+pub struct Message<'r> {
+    /// Always `diagnostic`.
+    #[serde(rename = "$message_type")]
+    pub message_type: &'static str,
+    /// The main message.
+    pub message: &'r str,
+    /// The optional diagnostic code.
+    pub code: Option<Code<'r>>,
+    /// `error: internal compiler error`, `error`, `warning`, `note` or `help`.
+    pub level: &'static str,
+    /// The labeled regions.
+    pub spans: Vec<Span<'r>>,
+    /// Notes and suggestions.
+    pub children: Vec<Message<'r>>,
+    /// The plain-text rendering of the diagnostic, `null` for children.
+    pub rendered: Option<String>,
+}
+
+/// A rustc-style diagnostic code.
+#[derive(Debug, Serialize)]
+pub struct Code<'r> {
+    /// The code.
+    pub code: String,
+    /// The long-form explanation of the code.
+    pub explanation: Option<String>,
+    /// A link to the explanation of the code, omitted if unknown.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<&'r str>,
+}
+
+/// A rustc-style labeled region.
+#[derive(Debug, Serialize)]
+pub struct Span<'r> {
+    /// The name of the file.
+    pub file_name: &'r str,
+    /// The byte offset of the start of the region.
+    pub byte_start: usize,
+    /// The byte offset of the end of the region (exclusive).
+    pub byte_end: usize,
+    /// 1-based line number of the start of the region.
+    pub line_start: usize,
+    /// 1-based line number of the end of the region.
+    pub line_end: usize,
+    /// 1-based character column of the start of the region.
+    pub column_start: usize,
+    /// 1-based character column of the end of the region (exclusive).
+    pub column_end: usize,
+    /// Whether this is the primary region.
+    pub is_primary: bool,
+    /// The source lines of the region.
+    pub text: Vec<SpanLine<'r>>,
+    /// The message of the region.
+    pub label: Option<&'r str>,
+    /// The suggested replacement of the region.
+    pub suggested_replacement: Option<&'r str>,
+    /// How the suggestion may be applied.
+    pub suggestion_applicability: Option<&'static str>,
+    /// Always `null`.
+    pub expansion: Option<()>,
+}
+
+/// One source line of a [`Span`].
+#[derive(Debug, Serialize)]
+pub struct SpanLine<'r> {
+    /// The content of the line.
+    pub text: &'r str,
+    /// 1-based character column where the highlight starts.
+    pub highlight_start: usize,
+    /// 1-based character column where the highlight ends (exclusive).
+    pub highlight_end: usize,
+}
+
+/// A diagnostic reporting renderer that emits one cargo `compiler-message` JSON object per line,
+/// so that tools parsing `cargo --message-format=json` can consume the diagnostics.
+pub struct CargoJson<W = Stdout> {
+    writer: W,
+    package_id: Option<String>,
+}
+
+impl Default for CargoJson {
+    fn default() -> Self {
+        Self::with_writer(std::io::stdout())
+    }
+}
+
+impl<W> CargoJson<W>
+where
+    W: Write,
+{
+    /// Create a new `CargoJson` that renders into `writer`.
+    pub fn with_writer(writer: W) -> Self {
+        Self {
+            writer,
+            package_id: None,
+        }
+    }
+
+    /// Set the `package_id` of the envelope.
+    pub fn with_package_id<P: Into<String>>(mut self, package_id: P) -> Self {
+        self.package_id = Some(package_id.into());
+        self
+    }
+
+    /// Consume this `CargoJson`, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Convert `diagnostic` into a cargo `compiler-message`.
+pub fn to_compiler_message<'r, F>(
+    files: &'r F,
+    diagnostic: &'r Diagnostic<'_>,
+    package_id: Option<&'r str>,
+) -> std::result::Result<CompilerMessage<'r>, FilesError>
+where
+    F: Files,
+{
+    let mut spans = vec![];
+    let mut children = vec![];
+
+    for label in &diagnostic.labels {
+        spans.push(to_span(
+            files,
+            label,
+            &label.primary.byte_range(files, label.id)?,
+            Some(&label.primary.message),
+            label.style == LabelStyle::Primary,
+        )?);
+
+        for region in &label.secondary {
+            spans.push(to_span(
+                files,
+                label,
+                &region.byte_range(files, label.id)?,
+                Some(&region.message),
+                false,
+            )?);
+        }
+
+        for suggestion in &label.suggestions {
+            let mut span = to_span(files, label, &suggestion.range, None, true)?;
+
+            span.suggested_replacement = Some(&suggestion.replacement);
+            span.suggestion_applicability = Some(suggestion.applicability.as_str());
+
+            children.push(child("help", "try this", vec![span]));
+        }
+    }
+
+    for note in &diagnostic.nodes {
+        children.push(child("note", note, vec![]));
+    }
+
+    for help in &diagnostic.helps {
+        children.push(child("help", help, vec![]));
+    }
+
+    Ok(CompilerMessage {
+        reason: "compiler-message",
+        package_id,
+        message: Message {
+            message_type: "diagnostic",
+            message: &diagnostic.message,
+            code: diagnostic.code.as_ref().map(|code| Code {
+                code: code.to_string(),
+                explanation: None,
+                url: diagnostic.url.as_deref(),
+            }),
+            level: match diagnostic.level {
+                Level::Bug => "error: internal compiler error",
+                level => level.as_str(),
+            },
+            spans,
+            children,
+            rendered: Some(diagnostic.to_plain_string(files)?),
+        },
+    })
+}
+
+fn child<'r>(level: &'static str, message: &'r str, spans: Vec<Span<'r>>) -> Message<'r> {
+    Message {
+        message_type: "diagnostic",
+        message,
+        code: None,
+        level,
+        spans,
+        children: vec![],
+        rendered: None,
+    }
+}
+
+fn to_span<'r, F>(
+    files: &'r F,
+    label: &Label<'_>,
+    range: &Range<usize>,
+    message: Option<&'r str>,
+    is_primary: bool,
+) -> std::result::Result<Span<'r>, FilesError>
+where
+    F: Files,
+{
+    let location = files.to_location(label.id, range)?;
+
+    // rustc counts columns in characters, not bytes.
+    let column =
+        |line: usize, cols: usize| char_column(files.try_line(label.id, line).unwrap_or(""), cols);
+
+    let text = (location.start.lines..=location.end.lines)
+        .map(|line| {
+            let text = files.try_line(label.id, line).unwrap_or("");
+
+            SpanLine {
+                text,
+                highlight_start: if line == location.start.lines {
+                    char_column(text, location.start.cols)
+                } else {
+                    1
+                },
+                highlight_end: if line == location.end.lines {
+                    char_column(text, location.end.cols)
+                } else {
+                    text.chars().count() + 1
+                },
+            }
+        })
+        .collect();
+
+    Ok(Span {
+        file_name: files.to_file_name(label.id)?,
+        byte_start: range.start,
+        byte_end: range.end,
+        line_start: location.start.lines,
+        line_end: location.end.lines,
+        column_start: column(location.start.lines, location.start.cols),
+        column_end: column(location.end.lines, location.end.cols),
+        is_primary,
+        text,
+        label: message,
+        suggested_replacement: None,
+        suggestion_applicability: None,
+        expansion: None,
+    })
+}
+
+/// Converts the 1-based byte column `cols` of `line` into a 1-based character column.
+fn char_column(line: &str, cols: usize) -> usize {
+    line.get(..cols.saturating_sub(1))
+        .unwrap_or(line)
+        .chars()
+        .count()
+        + 1
+}
+
+impl<W> Renderer for CargoJson<W>
+where
+    W: Write,
+{
+    type Error = std::io::Error;
+
+    fn render<'a, F, D>(&mut self, files: &F, diagnostic: D) -> Result<()>
+    where
+        F: Files,
+        Diagnostic<'a>: From<D>,
+    {
+        let diagnostic: Diagnostic<'a> = diagnostic.into();
+
+        check_files(files, &diagnostic)?;
+
+        let message = to_compiler_message(files, &diagnostic, self.package_id.as_deref())?;
+
+        serde_json::to_writer(&mut self.writer, &message)?;
+
+        writeln!(&mut self.writer)?;
+
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::{Applicability, Diagnostic, Label, Renderer, SourceCodes};
+
+    use super::CargoJson;
+
+    #[test]
+    fn test_cargo_json() {
+        let mut files = SourceCodes::default();
+
+        files.add("src/main.rs", "fn main() {\n    let x = 1\n}\n");
+
+        let mut renderer = CargoJson::with_writer(vec![]).with_package_id("demo 0.1.0");
+
+        renderer
+            .render(
+                &files,
+                Diagnostic::error("expected `;`")
+                    .with_code(1)
+                    .with_label(
+                        Label::new(0, 16..25, "missing `;`").with_secondary(26..27, "before this"),
+                    )
+                    .with_note("statements end with `;`"),
+            )
+            .unwrap();
+
+        let output = String::from_utf8(renderer.into_inner()).unwrap();
+
+        assert_eq!(output.lines().count(), 1);
+
+        let value: Value = serde_json::from_str(&output).unwrap();
+
+        assert_eq!(value["reason"], "compiler-message");
+        assert_eq!(value["package_id"], "demo 0.1.0");
+
+        let message = &value["message"];
+
+        assert_eq!(message["$message_type"], "diagnostic");
+        assert_eq!(message["level"], "error");
+        assert_eq!(message["message"], "expected `;`");
+        assert_eq!(message["code"]["code"], "000001");
+
+        let span = &message["spans"][0];
+
+        assert_eq!(span["file_name"], "src/main.rs");
+        assert_eq!(span["byte_start"], 16);
+        assert_eq!(span["byte_end"], 25);
+        assert_eq!(span["line_start"], 2);
+        assert_eq!(span["line_end"], 2);
+        assert_eq!(span["column_start"], 5);
+        assert_eq!(span["column_end"], 14);
+        assert_eq!(span["is_primary"], true);
+        assert_eq!(span["label"], "missing `;`");
+        assert_eq!(span["text"][0]["text"], "    let x = 1");
+
+        assert_eq!(message["spans"][1]["is_primary"], false);
+
+        assert_eq!(message["children"][0]["level"], "note");
+        assert_eq!(message["children"][0]["message"], "statements end with `;`");
+
+        assert!(
+            message["rendered"]
+                .as_str()
+                .unwrap()
+                .starts_with("error[000001]: expected `;`")
+        );
+    }
+
+    #[test]
+    fn test_char_columns() {
+        let mut files = SourceCodes::default();
+
+        files.add("src/main.rs", "let s = \"é\"; x\n");
+
+        let mut renderer = CargoJson::with_writer(vec![]);
+
+        renderer
+            .render(
+                &files,
+                Diagnostic::error("unresolved name").with_label(Label::new(0, 14..15, "not found")),
+            )
+            .unwrap();
+
+        let value: Value = serde_json::from_slice(&renderer.into_inner()).unwrap();
+
+        let span = &value["message"]["spans"][0];
+
+        // `é` is 2 bytes but 1 character.
+        assert_eq!(span["byte_start"], 14);
+        assert_eq!(span["column_start"], 14);
+        assert_eq!(span["column_end"], 15);
+        assert_eq!(span["text"][0]["highlight_start"], 14);
+        assert_eq!(span["text"][0]["highlight_end"], 15);
+    }
+
+    #[test]
+    fn test_code_url() {
+        let files = SourceCodes::default();
+
+        let mut renderer = CargoJson::with_writer(vec![]);
+
+        renderer
+            .render(
+                &files,
+                Diagnostic::error("mismatched types")
+                    .with_code("E0308")
+                    .with_code_url("https://doc.rust-lang.org/error_codes/E0308.html"),
+            )
+            .unwrap();
+
+        let value: Value = serde_json::from_slice(&renderer.into_inner()).unwrap();
+
+        assert_eq!(value["message"]["code"]["code"], "E0308");
+        assert_eq!(
+            value["message"]["code"]["url"],
+            "https://doc.rust-lang.org/error_codes/E0308.html"
+        );
+    }
+
+    #[test]
+    fn test_suggestion_applicability() {
+        let mut files = SourceCodes::default();
+
+        files.add("src/main.rs", "fn main() {\n    let x = 1\n}\n");
+
+        let mut renderer = CargoJson::with_writer(vec![]);
+
+        renderer
+            .render(
+                &files,
+                Diagnostic::error("expected `;`").with_label(
+                    Label::new(0, 16..25, "missing `;`")
+                        .with_applicable_suggestion(25..25, ";", Applicability::MachineApplicable)
+                        .with_suggestion(16..20, ""),
+                ),
+            )
+            .unwrap();
+
+        let value: Value = serde_json::from_slice(&renderer.into_inner()).unwrap();
+
+        let children = &value["message"]["children"];
+
+        assert_eq!(children[0]["level"], "help");
+        assert_eq!(children[0]["spans"][0]["suggested_replacement"], ";");
+        assert_eq!(
+            children[0]["spans"][0]["suggestion_applicability"],
+            "MachineApplicable"
+        );
+        assert_eq!(
+            children[1]["spans"][0]["suggestion_applicability"],
+            "Unspecified"
+        );
+    }
+}
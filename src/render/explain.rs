@@ -0,0 +1,98 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use crate::Code;
+
+/// A registry of long-form explanations of diagnostic codes, e.g. to power an
+/// `explain <code>` subcommand.
+#[derive(Debug, Clone)]
+pub struct CodeExplanations {
+    command: Cow<'static, str>,
+    /// Keyed by [`Code::name`], e.g. `308` or `E0308`, as printed in the hint and typed by users.
+    explanations: HashMap<String, String>,
+}
+
+impl Default for CodeExplanations {
+    fn default() -> Self {
+        Self {
+            command: "explain".into(),
+            explanations: HashMap::new(),
+        }
+    }
+}
+
+impl CodeExplanations {
+    /// Set the command that users run to read an explanation, `explain` by default.
+    ///
+    /// It is shown as ``run `{command} {code}` for details`` under diagnostics with
+    /// a registered code.
+    pub fn with_command<C>(mut self, command: C) -> Self
+    where
+        Cow<'static, str>: From<C>,
+    {
+        self.command = command.into();
+        self
+    }
+
+    /// Register the explanation of `code`, in markdown, replacing any previous one.
+    ///
+    /// Numeric codes are registered unpadded, so `308` and `"308"` are the same code.
+    pub fn register<'c, C, M>(&mut self, code: C, markdown: M) -> &mut Self
+    where
+        Code<'c>: From<C>,
+        String: From<M>,
+    {
+        self.explanations
+            .insert(Code::from(code).name().into_owned(), markdown.into());
+        self
+    }
+
+    /// Returns the explanation of `code`, if registered.
+    pub fn explain<'c, C>(&self, code: C) -> Option<&str>
+    where
+        Code<'c>: From<C>,
+    {
+        self.explanations
+            .get(Code::from(code).name().as_ref())
+            .map(String::as_str)
+    }
+
+    /// Returns the command that users run to read an explanation.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain() {
+        let mut explanations = CodeExplanations::default().with_command("mytool explain");
+
+        explanations.register(308, "Expected type did not match the received type.");
+
+        assert_eq!(
+            explanations.explain(308),
+            Some("Expected type did not match the received type.")
+        );
+        assert_eq!(explanations.explain(1), None);
+
+        // the code printed in the hint, e.g. typed as `mytool explain 308`, finds it.
+        let printed = Code::from(308).name().into_owned();
+
+        assert_eq!(printed, "308");
+        assert_eq!(
+            explanations.explain(printed),
+            Some("Expected type did not match the received type.")
+        );
+
+        explanations.register("E0425", "An unresolved name was used.");
+
+        assert_eq!(
+            explanations.explain("E0425"),
+            Some("An unresolved name was used.")
+        );
+        assert_eq!(explanations.command(), "mytool explain");
+    }
+}
@@ -1,8 +1,18 @@
-use std::{fmt::Display, ops::Range};
+use std::{
+    borrow::Cow,
+    cell::{OnceCell, RefCell},
+    collections::HashMap,
+    fmt::Display,
+    io,
+    ops::Range,
+    path::PathBuf,
+};
 
 use crate::FileId;
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+/// A line/column position in a source file, both 1-based.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Location {
     /// The line number in the source file.
     pub lines: usize,
@@ -16,150 +26,615 @@ impl Display for Location {
     }
 }
 
+/// Error returned by [`Files`] methods for ids, offsets or lines that do not exist.
+#[derive(Debug, PartialEq, Eq, Clone, thiserror::Error)]
+pub enum FilesError {
+    /// No source is registered for the file id.
+    #[error("file id {} not found", .0.0)]
+    UnknownFile(FileId),
+    /// A byte offset is past the end of the file.
+    #[error("offset {offset} out of range in file id {}", .id.0)]
+    OffsetOutOfRange {
+        /// The file of the offset.
+        id: FileId,
+        /// The byte offset.
+        offset: usize,
+    },
+    /// A byte offset is inside of a multi-byte UTF-8 sequence.
+    #[error("offset {offset} is not on a char boundary in file id {}", .id.0)]
+    NotCharBoundary {
+        /// The file of the offset.
+        id: FileId,
+        /// The byte offset.
+        offset: usize,
+    },
+    /// A line number is 0 or past the last line of the file.
+    #[error("line {line} out of range in file id {}", .id.0)]
+    LineOutOfRange {
+        /// The file of the line.
+        id: FileId,
+        /// The 1-based line number.
+        line: usize,
+    },
+    /// A column is 0 or past the end of its line, one past the last byte being the line end.
+    #[error("column {column} out of range on line {line} in file id {}", .id.0)]
+    ColumnOutOfRange {
+        /// The file of the column.
+        id: FileId,
+        /// The 1-based line number.
+        line: usize,
+        /// The 1-based (byte) column.
+        column: usize,
+    },
+    /// The source of a file could not be read.
+    #[error("failed to read file id {}: {message}", .id.0)]
+    Io {
+        /// The file that failed to load.
+        id: FileId,
+        /// The kind of the underlying I/O error.
+        kind: io::ErrorKind,
+        /// The message of the underlying I/O error.
+        message: String,
+    },
+    /// Rendering into a string failed for another reason than a `Files` method, e.g. a label
+    /// whose file was rejected up front, see [`Diagnostic::to_plain_string`](crate::Diagnostic::to_plain_string).
+    #[error("{message}")]
+    Render {
+        /// The kind of the underlying I/O error.
+        kind: io::ErrorKind,
+        /// The message of the underlying I/O error.
+        message: String,
+    },
+}
+
+/// Renderers writing to [`std::io::Write`] report invalid spans as [`InvalidInput`](io::ErrorKind::InvalidInput),
+/// and read failures with their original kind.
+impl From<FilesError> for io::Error {
+    fn from(err: FilesError) -> Self {
+        match err {
+            FilesError::Io { kind, .. } | FilesError::Render { kind, .. } => {
+                io::Error::new(kind, err)
+            }
+            err => io::Error::new(io::ErrorKind::InvalidInput, err),
+        }
+    }
+}
+
 /// The source manager must implement this trait.
 pub trait Files {
     /// Convert range into `Location` range.
-    fn to_location(&self, id: FileId, range: &Range<usize>) -> Range<Location>;
+    fn to_location(&self, id: FileId, range: &Range<usize>) -> Result<Range<Location>, FilesError>;
+
+    /// Convert `Location` into byte offset.
+    fn to_offset(&self, id: FileId, location: Location) -> Result<usize, FilesError>;
 
     /// Read file content by line number.
-    fn as_str(&self, id: FileId, lines: usize) -> &str;
+    fn as_str(&self, id: FileId, lines: usize) -> Result<&str, FilesError>;
+
+    /// Read file content by line number, or `None` if there is no such line.
+    fn try_line(&self, id: FileId, lines: usize) -> Option<&str> {
+        self.as_str(id, lines).ok()
+    }
+
+    /// Returns the byte range of the content of line `lines`, excluding the line break.
+    ///
+    /// The default implementation derives it from [`to_offset`](Self::to_offset) and
+    /// [`as_str`](Self::as_str).
+    fn line_range(&self, id: FileId, lines: usize) -> Result<Range<usize>, FilesError> {
+        let start = self.to_offset(id, Location { lines, cols: 1 })?;
+
+        Ok(start..start + self.as_str(id, lines)?.len())
+    }
 
     /// Convert file id to file name.
-    fn to_file_name(&self, id: FileId) -> &str;
+    fn to_file_name(&self, id: FileId) -> Result<&str, FilesError>;
+
+    /// Returns true if `id` references a registered file.
+    ///
+    /// Renderers check this before rendering, to report a descriptive error up front.
+    fn contains(&self, id: FileId) -> bool {
+        self.to_file_name(id).is_ok()
+    }
+
+    /// Returns the number of lines of the file, which is also its last 1-based line number.
+    ///
+    /// A trailing line break starts an empty last line, as in [`as_str`](Self::as_str).
+    /// The default implementation probes the lines with [`try_line`](Self::try_line).
+    fn line_count(&self, id: FileId) -> Result<usize, FilesError> {
+        self.to_file_name(id)?;
+
+        let mut count = 0;
+
+        while self.try_line(id, count + 1).is_some() {
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Returns the source text of `range`, which may span several lines.
+    ///
+    /// The default implementation joins the parts of the spanned lines with `\n`,
+    /// implementations that keep the whole content should override it to borrow the text.
+    fn source_slice(&self, id: FileId, range: &Range<usize>) -> Result<Cow<'_, str>, FilesError> {
+        if range.start > range.end {
+            return Err(FilesError::OffsetOutOfRange {
+                id,
+                offset: range.start,
+            });
+        }
+
+        let location = self.to_location(id, range)?;
+
+        let mut slice = String::new();
+
+        for lines in location.start.lines..=location.end.lines {
+            let line = self.as_str(id, lines)?;
+
+            let start = if lines == location.start.lines {
+                location.start.cols - 1
+            } else {
+                slice.push('\n');
+                0
+            };
+
+            let end = if lines == location.end.lines {
+                location.end.cols - 1
+            } else {
+                line.len()
+            };
+
+            slice.push_str(&line[start.min(line.len())..end.min(line.len())]);
+        }
+
+        Ok(Cow::Owned(slice))
+    }
+}
+
+impl<T> Files for &T
+where
+    T: Files + ?Sized,
+{
+    fn to_location(&self, id: FileId, range: &Range<usize>) -> Result<Range<Location>, FilesError> {
+        (**self).to_location(id, range)
+    }
+
+    fn to_offset(&self, id: FileId, location: Location) -> Result<usize, FilesError> {
+        (**self).to_offset(id, location)
+    }
+
+    fn as_str(&self, id: FileId, lines: usize) -> Result<&str, FilesError> {
+        (**self).as_str(id, lines)
+    }
+
+    fn try_line(&self, id: FileId, lines: usize) -> Option<&str> {
+        (**self).try_line(id, lines)
+    }
+
+    fn line_range(&self, id: FileId, lines: usize) -> Result<Range<usize>, FilesError> {
+        (**self).line_range(id, lines)
+    }
+
+    fn to_file_name(&self, id: FileId) -> Result<&str, FilesError> {
+        (**self).to_file_name(id)
+    }
+
+    fn contains(&self, id: FileId) -> bool {
+        (**self).contains(id)
+    }
+
+    fn line_count(&self, id: FileId) -> Result<usize, FilesError> {
+        (**self).line_count(id)
+    }
+
+    fn source_slice(&self, id: FileId, range: &Range<usize>) -> Result<Cow<'_, str>, FilesError> {
+        (**self).source_slice(id, range)
+    }
 }
 
 /// A source file with line break index.
-struct ParsedFile {
+struct ParsedFile<'a> {
     line_break_offsets: Vec<usize>,
-    file_name: String,
-    content: String,
+    file_name: Cow<'a, str>,
+    content: Cow<'a, str>,
 }
 
-impl ParsedFile {
-    fn new(file_name: &str, content: &str) -> Self {
+impl<'a> ParsedFile<'a> {
+    fn new(file_name: Cow<'a, str>, content: Cow<'a, str>) -> Self {
+        // lines end with `\n`, `\r\n` or, in classic Mac files, a lone `\r`. The offset of
+        // the last byte of each line break is recorded.
+        let bytes = content.as_bytes();
+
         let mut line_break_offsets = vec![];
-        for (idx, c) in content.as_bytes().iter().enumerate() {
-            if *c == b'\n' {
+        for (idx, c) in bytes.iter().enumerate() {
+            if *c == b'\n' || (*c == b'\r' && bytes.get(idx + 1) != Some(&b'\n')) {
                 line_break_offsets.push(idx);
             }
         }
 
         Self {
             line_break_offsets,
-            content: content.to_string(),
-            file_name: file_name.to_string(),
+            content,
+            file_name,
         }
     }
 
-    fn location(&self, range: &Range<usize>) -> Range<Location> {
-        let start = self
-            .do_location(range.start)
-            .expect(&format!("location(start): out of range {}", range.start));
+    fn location(&self, id: FileId, range: &Range<usize>) -> Result<Range<Location>, FilesError> {
+        let location = |offset| {
+            let location = self
+                .do_location(offset)
+                .ok_or(FilesError::OffsetOutOfRange { id, offset })?;
+
+            if !self.content.is_char_boundary(offset) {
+                return Err(FilesError::NotCharBoundary { id, offset });
+            }
 
-        let end = self
-            .do_location(range.end)
-            .expect(&format!("location(end): out of range {}", range.end));
+            Ok(location)
+        };
 
-        start..end
+        Ok(location(range.start)?..location(range.end)?)
     }
 
-    fn as_str(&self, lines: usize) -> &str {
-        assert!(lines > 0, "lines must greater than 0.");
-        let lines = lines - 1;
+    fn offset(&self, id: FileId, location: Location) -> Result<usize, FilesError> {
+        let line = self
+            .line_range(location.lines)
+            .ok_or(FilesError::LineOutOfRange {
+                id,
+                line: location.lines,
+            })?;
 
-        assert!(
-            lines < self.line_break_offsets.len() + 1,
-            "lines out of range."
-        );
+        if location.cols == 0 || location.cols > line.len() + 1 {
+            return Err(FilesError::ColumnOutOfRange {
+                id,
+                line: location.lines,
+                column: location.cols,
+            });
+        }
+
+        let offset = line.start + location.cols - 1;
 
-        if self.line_break_offsets.is_empty() {
-            return self.content.as_str();
+        if offset > self.content.len() {
+            return Err(FilesError::OffsetOutOfRange { id, offset });
         }
 
-        if lines == 0 {
-            return &self.content[..self.line_break_offsets[0]];
+        if !self.content.is_char_boundary(offset) {
+            return Err(FilesError::NotCharBoundary { id, offset });
         }
 
-        return &self.content
-            [self.line_break_offsets[lines - 1] + 1..self.line_break_offsets[lines]];
+        Ok(offset)
     }
 
-    fn do_location(&self, offset: usize) -> Option<Location> {
-        if self.line_break_offsets.is_empty() {
-            return Some(Location {
-                lines: 1,
-                cols: offset + 1,
+    fn as_str(&self, id: FileId, lines: usize) -> Result<&str, FilesError> {
+        self.try_line(lines)
+            .ok_or(FilesError::LineOutOfRange { id, line: lines })
+    }
+
+    fn try_line(&self, lines: usize) -> Option<&str> {
+        self.line_range(lines).map(|range| &self.content[range])
+    }
+
+    fn line_count(&self) -> usize {
+        self.line_break_offsets.len() + 1
+    }
+
+    fn slice(&self, id: FileId, range: &Range<usize>) -> Result<Cow<'_, str>, FilesError> {
+        if range.start > range.end {
+            return Err(FilesError::OffsetOutOfRange {
+                id,
+                offset: range.start,
             });
         }
 
-        for (idx, o) in self.line_break_offsets.iter().enumerate() {
-            if offset <= *o {
-                if idx != 0 {
-                    let cols = offset - self.line_break_offsets[idx - 1] - 1;
+        for offset in [range.start, range.end] {
+            if offset > self.content.len() {
+                return Err(FilesError::OffsetOutOfRange { id, offset });
+            }
 
-                    return Some(Location {
-                        lines: idx + 1,
-                        cols: cols + 1,
-                    });
-                }
+            if !self.content.is_char_boundary(offset) {
+                return Err(FilesError::NotCharBoundary { id, offset });
             }
         }
 
-        None
+        Ok(Cow::Borrowed(&self.content[range.clone()]))
+    }
+
+    fn line_range(&self, lines: usize) -> Option<Range<usize>> {
+        let index = lines.checked_sub(1)?;
+
+        if index > self.line_break_offsets.len() {
+            return None;
+        }
+
+        let start = if index == 0 {
+            0
+        } else {
+            self.line_break_offsets[index - 1] + 1
+        };
+
+        let end = match self.line_break_offsets.get(index) {
+            // the `\r` of a `\r\n` is not part of the line content.
+            Some(&end) if end > start && self.content.as_bytes()[end - 1..=end] == *b"\r\n" => {
+                end - 1
+            }
+            Some(&end) => end,
+            None => self.content.len(),
+        };
+
+        Some(start..end)
+    }
+
+    fn do_location(&self, offset: usize) -> Option<Location> {
+        if offset > self.content.len() {
+            return None;
+        }
+
+        // a line break belongs to the line it ends.
+        let index = self.line_break_offsets.partition_point(|o| *o < offset);
+
+        let line = self.line_range(index + 1)?;
+
+        // the `\n` of a `\r\n` is clamped to the line end, as the `\r` is.
+        Some(Location {
+            lines: index + 1,
+            cols: (offset - line.start).min(line.len()) + 1,
+        })
     }
 }
 
 /// A simple in-memory source codes manager.
+///
+/// Names and contents can be borrowed for `'a`, so transient buffers can back the sources
+/// without allocation.
 #[derive(Default)]
-pub struct SourceCodes(Vec<ParsedFile>);
+pub struct SourceCodes<'a>(Vec<ParsedFile<'a>>);
 
-impl SourceCodes {
+impl<'a> SourceCodes<'a> {
     /// Add a new soure file content.
-    pub fn add<N: AsRef<str>, C: AsRef<str>>(&mut self, name: N, content: C) -> FileId {
+    pub fn add<N, C>(&mut self, name: N, content: C) -> FileId
+    where
+        Cow<'a, str>: From<N> + From<C>,
+    {
         let id = self.0.len();
 
-        self.0
-            .push(ParsedFile::new(name.as_ref(), content.as_ref()));
+        self.0.push(ParsedFile::new(name.into(), content.into()));
 
         FileId(id)
     }
+
+    /// Returns the lines of file `id` in order, the first one being line 1, without their
+    /// line breaks.
+    pub fn lines(&self, id: FileId) -> Result<impl Iterator<Item = &str> + '_, FilesError> {
+        let file = self.file(id)?;
+
+        Ok((1..=file.line_count()).filter_map(|lines| file.try_line(lines)))
+    }
+
+    fn file(&self, id: FileId) -> Result<&ParsedFile<'a>, FilesError> {
+        self.0.get(id.0).ok_or(FilesError::UnknownFile(id))
+    }
 }
 
-impl Files for SourceCodes {
-    fn to_location(&self, id: FileId, range: &Range<usize>) -> Range<Location> {
-        assert!(
-            id.0 < self.0.len(),
-            "InMemoryFiles::location: file id({}) out of range",
-            id.0
-        );
+impl Files for SourceCodes<'_> {
+    fn to_location(&self, id: FileId, range: &Range<usize>) -> Result<Range<Location>, FilesError> {
+        self.file(id)?.location(id, range)
+    }
 
-        let file = &self.0[id.0];
+    fn to_offset(&self, id: FileId, location: Location) -> Result<usize, FilesError> {
+        self.file(id)?.offset(id, location)
+    }
 
-        file.location(range)
+    fn as_str(&self, id: FileId, lines: usize) -> Result<&str, FilesError> {
+        self.file(id)?.as_str(id, lines)
     }
 
-    fn as_str(&self, id: FileId, lines: usize) -> &str {
-        assert!(
-            id.0 < self.0.len(),
-            "InMemoryFiles::as_str: file id({}) out of range",
-            id.0
-        );
+    fn try_line(&self, id: FileId, lines: usize) -> Option<&str> {
+        self.0.get(id.0)?.try_line(lines)
+    }
 
-        let file = &self.0[id.0];
+    fn line_range(&self, id: FileId, lines: usize) -> Result<Range<usize>, FilesError> {
+        self.file(id)?
+            .line_range(lines)
+            .ok_or(FilesError::LineOutOfRange { id, line: lines })
+    }
 
-        file.as_str(lines)
+    fn to_file_name(&self, id: FileId) -> Result<&str, FilesError> {
+        Ok(&self.file(id)?.file_name)
     }
 
-    fn to_file_name(&self, id: FileId) -> &str {
-        assert!(
-            id.0 < self.0.len(),
-            "InMemoryFiles::to_file_name: file id({}) out of range",
-            id.0
-        );
+    fn contains(&self, id: FileId) -> bool {
+        id.0 < self.0.len()
+    }
 
-        let file = &self.0[id.0];
+    fn line_count(&self, id: FileId) -> Result<usize, FilesError> {
+        Ok(self.file(id)?.line_count())
+    }
 
-        &file.file_name
+    fn source_slice(&self, id: FileId, range: &Range<usize>) -> Result<Cow<'_, str>, FilesError> {
+        self.file(id)?.slice(id, range)
+    }
+}
+
+/// A cached result of the loader of [`LazyFiles`].
+type Slot = OnceCell<Option<ParsedFile<'static>>>;
+
+/// A source codes manager that loads each file on first access, from a loader callback.
+///
+/// The loader maps a file id to its name and content, or `None` if there is no such file.
+/// It is called at most once per id, and the parsed result is cached.
+pub struct LazyFiles<L> {
+    loader: L,
+    /// The slot of each requested id, in request order.
+    slots: RefCell<HashMap<FileId, usize>>,
+    /// Slot `n` lives in chunk `log2(n + 1)`, chunk `k` holds `2^k` slots. Chunks are only
+    /// ever allocated, never moved, so loaded files stay in place while others are loaded.
+    chunks: [OnceCell<Box<[Slot]>>; usize::BITS as usize],
+}
+
+impl<L> LazyFiles<L>
+where
+    L: Fn(FileId) -> Option<(String, String)>,
+{
+    /// Create a new `LazyFiles` that loads sources with `loader`.
+    pub fn new(loader: L) -> Self {
+        Self {
+            loader,
+            slots: RefCell::new(HashMap::new()),
+            chunks: std::array::from_fn(|_| OnceCell::new()),
+        }
+    }
+
+    /// Returns the parsed file `id`, loading it on first access.
+    fn get(&self, id: FileId) -> Option<&ParsedFile<'static>> {
+        let slot = {
+            let mut slots = self.slots.borrow_mut();
+            let next = slots.len();
+
+            *slots.entry(id).or_insert(next)
+        };
+
+        let chunk = (slot + 1).ilog2() as usize;
+
+        let cells = self.chunks[chunk]
+            .get_or_init(|| (0..1usize << chunk).map(|_| OnceCell::new()).collect());
+
+        cells[slot + 1 - (1 << chunk)]
+            .get_or_init(|| {
+                (self.loader)(id)
+                    .map(|(name, content)| ParsedFile::new(name.into(), content.into()))
+            })
+            .as_ref()
+    }
+
+    fn file(&self, id: FileId) -> Result<&ParsedFile<'static>, FilesError> {
+        self.get(id).ok_or(FilesError::UnknownFile(id))
+    }
+}
+
+impl<L> Files for LazyFiles<L>
+where
+    L: Fn(FileId) -> Option<(String, String)>,
+{
+    fn to_location(&self, id: FileId, range: &Range<usize>) -> Result<Range<Location>, FilesError> {
+        self.file(id)?.location(id, range)
+    }
+
+    fn to_offset(&self, id: FileId, location: Location) -> Result<usize, FilesError> {
+        self.file(id)?.offset(id, location)
+    }
+
+    fn as_str(&self, id: FileId, lines: usize) -> Result<&str, FilesError> {
+        self.file(id)?.as_str(id, lines)
+    }
+
+    fn try_line(&self, id: FileId, lines: usize) -> Option<&str> {
+        self.get(id)?.try_line(lines)
+    }
+
+    fn line_range(&self, id: FileId, lines: usize) -> Result<Range<usize>, FilesError> {
+        self.file(id)?
+            .line_range(lines)
+            .ok_or(FilesError::LineOutOfRange { id, line: lines })
+    }
+
+    fn to_file_name(&self, id: FileId) -> Result<&str, FilesError> {
+        Ok(&self.file(id)?.file_name)
+    }
+
+    fn contains(&self, id: FileId) -> bool {
+        self.get(id).is_some()
+    }
+
+    fn line_count(&self, id: FileId) -> Result<usize, FilesError> {
+        Ok(self.file(id)?.line_count())
+    }
+
+    fn source_slice(&self, id: FileId, range: &Range<usize>) -> Result<Cow<'_, str>, FilesError> {
+        self.file(id)?.slice(id, range)
+    }
+}
+
+/// A source codes manager that reads each file from disk on first access.
+///
+/// Only the paths are stored up front, a file is read and parsed once, when a diagnostic
+/// first needs its content.
+#[derive(Default)]
+pub struct FsFiles(Vec<FsFile>);
+
+struct FsFile {
+    path: PathBuf,
+    name: String,
+    parsed: OnceCell<Result<ParsedFile<'static>, FilesError>>,
+}
+
+impl FsFiles {
+    /// Add the source file at `path`, without reading it.
+    pub fn add<P: Into<PathBuf>>(&mut self, path: P) -> FileId {
+        let path = path.into();
+
+        self.0.push(FsFile {
+            name: path.to_string_lossy().into_owned(),
+            path,
+            parsed: OnceCell::new(),
+        });
+
+        FileId(self.0.len() - 1)
+    }
+
+    /// Returns the parsed file `id`, reading it on first access.
+    fn file(&self, id: FileId) -> Result<&ParsedFile<'static>, FilesError> {
+        let file = self.0.get(id.0).ok_or(FilesError::UnknownFile(id))?;
+
+        file.parsed
+            .get_or_init(|| {
+                std::fs::read_to_string(&file.path)
+                    .map(|content| ParsedFile::new(file.name.clone().into(), content.into()))
+                    .map_err(|err| FilesError::Io {
+                        id,
+                        kind: err.kind(),
+                        message: err.to_string(),
+                    })
+            })
+            .as_ref()
+            .map_err(Clone::clone)
+    }
+}
+
+impl Files for FsFiles {
+    fn to_location(&self, id: FileId, range: &Range<usize>) -> Result<Range<Location>, FilesError> {
+        self.file(id)?.location(id, range)
+    }
+
+    fn to_offset(&self, id: FileId, location: Location) -> Result<usize, FilesError> {
+        self.file(id)?.offset(id, location)
+    }
+
+    fn as_str(&self, id: FileId, lines: usize) -> Result<&str, FilesError> {
+        self.file(id)?.as_str(id, lines)
+    }
+
+    fn line_range(&self, id: FileId, lines: usize) -> Result<Range<usize>, FilesError> {
+        self.file(id)?
+            .line_range(lines)
+            .ok_or(FilesError::LineOutOfRange { id, line: lines })
+    }
+
+    fn to_file_name(&self, id: FileId) -> Result<&str, FilesError> {
+        self.0
+            .get(id.0)
+            .map(|file| file.name.as_str())
+            .ok_or(FilesError::UnknownFile(id))
+    }
+
+    fn contains(&self, id: FileId) -> bool {
+        id.0 < self.0.len()
+    }
+
+    fn line_count(&self, id: FileId) -> Result<usize, FilesError> {
+        Ok(self.file(id)?.line_count())
+    }
+
+    fn source_slice(&self, id: FileId, range: &Range<usize>) -> Result<Cow<'_, str>, FilesError> {
+        self.file(id)?.slice(id, range)
     }
 }
 
@@ -167,10 +642,35 @@ impl Files for SourceCodes {
 mod tests {
     use super::*;
 
+    /// Forwards the required methods only, to test the default implementations.
+    struct Defaults<'a>(&'a SourceCodes<'a>);
+
+    impl Files for Defaults<'_> {
+        fn to_location(
+            &self,
+            id: FileId,
+            range: &Range<usize>,
+        ) -> Result<Range<Location>, FilesError> {
+            self.0.to_location(id, range)
+        }
+
+        fn to_offset(&self, id: FileId, location: Location) -> Result<usize, FilesError> {
+            self.0.to_offset(id, location)
+        }
+
+        fn as_str(&self, id: FileId, lines: usize) -> Result<&str, FilesError> {
+            self.0.as_str(id, lines)
+        }
+
+        fn to_file_name(&self, id: FileId) -> Result<&str, FilesError> {
+            self.0.to_file_name(id)
+        }
+    }
+
     #[test]
     fn test_parse_file() {
         let file = ParsedFile::new(
-            "test",
+            "test".into(),
             unindent::unindent(
                 r#"
             module FizzBuzz where
@@ -191,12 +691,393 @@ mod tests {
                     _ _ => num
         "#,
             )
-            .as_str(),
+            .into(),
         );
 
         assert_eq!(file.line_break_offsets.len(), 16);
 
-        assert_eq!(file.as_str(1), "module FizzBuzz where");
-        assert_eq!(file.as_str(16), "        _ _ => num");
+        assert_eq!(file.try_line(1), Some("module FizzBuzz where"));
+        assert_eq!(file.try_line(16), Some("        _ _ => num"));
+    }
+
+    #[test]
+    fn test_try_line() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("main.rs", "fn main() {\n    let x = 1;\n}\n");
+
+        assert_eq!(files.try_line(id, 2), Some("    let x = 1;"));
+        assert_eq!(files.try_line(id, 0), None);
+        assert_eq!(files.try_line(id, 5), None);
+        assert_eq!(files.try_line(FileId(1), 1), None);
+    }
+
+    #[test]
+    fn test_last_line() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("two.txt", "a\nb");
+
+        assert_eq!(files.as_str(id, 1), Ok("a"));
+        assert_eq!(files.as_str(id, 2), Ok("b"));
+        assert_eq!(
+            files.as_str(id, 3),
+            Err(FilesError::LineOutOfRange { id, line: 3 })
+        );
+    }
+
+    #[test]
+    fn test_line_count() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("main.rs", "fn main() {\r\n    let x = 1;\r\n}");
+        let trailing = files.add("trailing.rs", "a\nb\n");
+
+        assert_eq!(files.line_count(id), Ok(3));
+        assert_eq!(
+            files.lines(id).unwrap().collect::<Vec<_>>(),
+            ["fn main() {", "    let x = 1;", "}"]
+        );
+
+        assert_eq!(files.line_count(trailing), Ok(3));
+        assert_eq!(files.lines(trailing).unwrap().last(), Some(""));
+
+        // the default implementation agrees with the override.
+        assert_eq!(Defaults(&files).line_count(id), Ok(3));
+        assert_eq!(
+            Defaults(&files).line_count(FileId(2)),
+            Err(FilesError::UnknownFile(FileId(2)))
+        );
+    }
+
+    #[test]
+    fn test_source_slice() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("main.rs", "fn main() {\n    let x = 1;\n}\n");
+
+        let slice = files.source_slice(id, &(3..27)).unwrap();
+
+        assert!(matches!(slice, Cow::Borrowed(_)));
+        assert_eq!(slice, "main() {\n    let x = 1;\n");
+
+        assert_eq!(
+            Defaults(&files).source_slice(id, &(3..27)).unwrap(),
+            "main() {\n    let x = 1;\n"
+        );
+        assert_eq!(
+            Defaults(&files).source_slice(id, &(16..21)).unwrap(),
+            "let x"
+        );
+
+        assert_eq!(
+            files.source_slice(id, &(3..40)),
+            Err(FilesError::OffsetOutOfRange { id, offset: 40 })
+        );
+        assert_eq!(
+            Defaults(&files).source_slice(id, &(3..40)),
+            Err(FilesError::OffsetOutOfRange { id, offset: 40 })
+        );
+    }
+
+    #[test]
+    fn test_line_range() {
+        let mut files = SourceCodes::default();
+
+        let content = "fn main() {\n    let x = 1;\n}";
+
+        let id = files.add("main.rs", content);
+
+        for lines in 1..=3 {
+            assert_eq!(
+                &content[files.line_range(id, lines).unwrap()],
+                files.as_str(id, lines).unwrap()
+            );
+        }
+
+        assert_eq!(files.line_range(id, 1), Ok(0..11));
+        assert_eq!(files.line_range(id, 3), Ok(27..28));
+
+        let id = files.add("trailing.rs", "a\nb\n");
+
+        assert_eq!(files.line_range(id, 2), Ok(2..3));
+        assert_eq!(files.line_range(id, 3), Ok(4..4));
+    }
+
+    #[test]
+    fn test_files_error() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("main.rs", "fn main() {}\n");
+
+        assert_eq!(
+            files.to_location(FileId(1), &(0..1)),
+            Err(FilesError::UnknownFile(FileId(1)))
+        );
+        assert_eq!(
+            files.to_location(id, &(0..20)),
+            Err(FilesError::OffsetOutOfRange { id, offset: 20 })
+        );
+        assert_eq!(
+            files.as_str(id, 3),
+            Err(FilesError::LineOutOfRange { id, line: 3 })
+        );
+        assert_eq!(
+            files.to_offset(id, Location { lines: 0, cols: 1 }),
+            Err(FilesError::LineOutOfRange { id, line: 0 })
+        );
+        assert_eq!(
+            files.to_offset(id, Location { lines: 2, cols: 5 }),
+            Err(FilesError::ColumnOutOfRange {
+                id,
+                line: 2,
+                column: 5
+            })
+        );
+        // the line end is the last column of a line.
+        assert_eq!(files.to_offset(id, Location { lines: 1, cols: 13 }), Ok(12));
+        assert_eq!(
+            files.to_offset(id, Location { lines: 1, cols: 14 }),
+            Err(FilesError::ColumnOutOfRange {
+                id,
+                line: 1,
+                column: 14
+            })
+        );
+    }
+
+    #[test]
+    fn test_char_boundary() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("fizz.txt", "fizz₁ : Nat\n");
+
+        let location = files.to_location(id, &(0..7)).unwrap();
+
+        assert_eq!(location.end.cols, 8);
+        assert_eq!(
+            files.to_location(id, &(0..5)),
+            Err(FilesError::NotCharBoundary { id, offset: 5 })
+        );
+        assert_eq!(
+            files.to_offset(id, Location { lines: 1, cols: 6 }),
+            Err(FilesError::NotCharBoundary { id, offset: 5 })
+        );
+    }
+
+    #[test]
+    fn test_location() {
+        let file = ParsedFile::new("main.rs".into(), "ab\ncd\nef".into());
+
+        let location = |offset| file.do_location(offset).map(|l| (l.lines, l.cols));
+
+        assert_eq!(location(0), Some((1, 1)));
+        assert_eq!(location(2), Some((1, 3)));
+        assert_eq!(location(3), Some((2, 1)));
+        assert_eq!(location(5), Some((2, 3)));
+        assert_eq!(location(7), Some((3, 2)));
+        assert_eq!(location(8), Some((3, 3)));
+        assert_eq!(location(9), None);
+
+        for offset in 0..=8 {
+            let location = file.do_location(offset).unwrap();
+
+            assert_eq!(file.offset(FileId(0), location), Ok(offset));
+        }
+
+        let file = ParsedFile::new("one.rs".into(), "abc".into());
+
+        assert_eq!(file.do_location(3).map(|l| l.cols), Some(4));
+    }
+
+    #[test]
+    fn test_location_large_file() {
+        let content = (0..100_000)
+            .map(|line| "x".repeat(line % 7) + "\n")
+            .collect::<String>();
+
+        let file = ParsedFile::new("large.txt".into(), content.as_str().into());
+
+        let mut lines = 1;
+        let mut line_start = 0;
+
+        for (offset, c) in content.char_indices() {
+            let location = file.do_location(offset).unwrap();
+
+            assert_eq!(
+                (location.lines, location.cols),
+                (lines, offset - line_start + 1)
+            );
+
+            if c == '\n' {
+                lines += 1;
+                line_start = offset + 1;
+            }
+        }
+
+        let end = file.do_location(content.len()).unwrap();
+
+        assert_eq!((end.lines, end.cols), (100_001, 1));
+    }
+
+    #[test]
+    fn test_crlf_line_endings() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("windows.txt", "a\r\nb\r\nc");
+
+        assert_eq!(files.as_str(id, 1), Ok("a"));
+        assert_eq!(files.as_str(id, 2), Ok("b"));
+        assert_eq!(files.as_str(id, 3), Ok("c"));
+        assert_eq!(files.line_range(id, 2), Ok(3..4));
+
+        let location = files.to_location(id, &(3..4)).unwrap();
+
+        assert_eq!((location.start.lines, location.start.cols), (2, 1));
+        assert_eq!((location.end.lines, location.end.cols), (2, 2));
+
+        // both bytes of a line break are at the line end.
+        for offset in [1, 2] {
+            let location = files.to_location(id, &(offset..offset)).unwrap();
+
+            assert_eq!((location.start.lines, location.start.cols), (1, 2));
+        }
+        assert_eq!(files.to_offset(id, Location { lines: 3, cols: 1 }), Ok(6));
+        // columns are 1-based.
+        assert_eq!(
+            files.to_offset(id, Location { lines: 3, cols: 0 }),
+            Err(FilesError::ColumnOutOfRange {
+                id,
+                line: 3,
+                column: 0
+            })
+        );
+
+        let id = files.add("mixed.txt", "a\nb\r\nc\rd");
+
+        assert_eq!(
+            (1..=4)
+                .map(|line| files.as_str(id, line).unwrap())
+                .collect::<Vec<_>>(),
+            ["a", "b", "c", "d"]
+        );
+    }
+
+    #[test]
+    fn test_cr_line_endings() {
+        let file = ParsedFile::new("mac.txt".into(), "a\rb\rc".into());
+
+        assert_eq!(file.line_break_offsets, [1, 3]);
+        assert_eq!(file.try_line(1), Some("a"));
+        assert_eq!(file.try_line(2), Some("b"));
+        assert_eq!(file.try_line(3), Some("c"));
+        assert_eq!(file.try_line(4), None);
+    }
+
+    #[test]
+    fn test_borrowed_source() {
+        let buffer = String::from("// editor buffer\nlet x = 1;\n");
+
+        let mut files = SourceCodes::default();
+
+        let id = files.add("untitled-1", &buffer[17..]);
+
+        assert_eq!(files.as_str(id, 1), Ok("let x = 1;"));
+        assert_eq!(files.to_file_name(id), Ok("untitled-1"));
+    }
+
+    #[test]
+    fn test_lazy_files() {
+        use std::cell::Cell;
+
+        let loads = Cell::new(0);
+
+        let files = LazyFiles::new(|id: FileId| {
+            loads.set(loads.get() + 1);
+
+            (id.0 < 2).then(|| {
+                (
+                    format!("file{}.txt", id.0),
+                    format!("// file {}\nlet x = 1;\n", id.0),
+                )
+            })
+        });
+
+        assert_eq!(loads.get(), 0);
+
+        assert_eq!(files.to_file_name(FileId(1)), Ok("file1.txt"));
+        assert_eq!(files.as_str(FileId(1), 2), Ok("let x = 1;"));
+        assert!(files.contains(FileId(1)));
+        assert_eq!(loads.get(), 1);
+
+        assert_eq!(files.as_str(FileId(0), 1), Ok("// file 0"));
+        assert_eq!(loads.get(), 2);
+
+        assert!(!files.contains(FileId(2)));
+        assert!(!files.contains(FileId(2)));
+        assert_eq!(loads.get(), 3);
+
+        assert_eq!(
+            files.to_file_name(FileId(2)),
+            Err(FilesError::UnknownFile(FileId(2)))
+        );
+    }
+
+    #[test]
+    fn test_lazy_files_stable() {
+        let files = LazyFiles::new(|id: FileId| {
+            Some((
+                format!("file{}.txt", id.0),
+                format!("line of file {}\n", id.0),
+            ))
+        });
+
+        let first = files.as_str(FileId(0), 1).unwrap();
+        let name = files.to_file_name(FileId(0)).unwrap();
+
+        // enough other files to grow the id map and allocate new chunks several times.
+        for id in 1..1000 {
+            assert_eq!(
+                files.as_str(FileId(id * 7), 1),
+                Ok(format!("line of file {}", id * 7).as_str())
+            );
+        }
+
+        assert_eq!(first, "line of file 0");
+        assert_eq!(name, "file0.txt");
+        assert_eq!(files.as_str(FileId(0), 1), Ok(first));
+    }
+
+    #[test]
+    fn test_fs_files() {
+        let path = std::env::temp_dir().join(format!("ceport-fs-files-{}.rs", std::process::id()));
+
+        std::fs::write(&path, "fn main() {\n    let x = 1;\n}\n").unwrap();
+
+        let mut files = FsFiles::default();
+
+        let id = files.add(&path);
+        let missing = files.add(path.with_extension("missing"));
+
+        assert_eq!(files.to_file_name(id), Ok(path.to_string_lossy().as_ref()));
+        assert_eq!(files.as_str(id, 2), Ok("    let x = 1;"));
+
+        // the content is cached after the first read.
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(files.line_range(id, 2), Ok(12..26));
+
+        assert!(files.contains(missing));
+        assert!(matches!(
+            files.as_str(missing, 1),
+            Err(FilesError::Io {
+                kind: io::ErrorKind::NotFound,
+                ..
+            })
+        ));
+        assert_eq!(
+            io::Error::from(files.as_str(missing, 1).unwrap_err()).kind(),
+            io::ErrorKind::NotFound
+        );
     }
 }
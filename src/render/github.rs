@@ -0,0 +1,154 @@
+//! A renderer implementation that emits GitHub Actions workflow commands.
+
+use std::io::{Result, Stdout, Write};
+
+use crate::{Diagnostic, Level};
+
+use super::{Files, Renderer, check_files};
+
+/// A diagnostic reporting renderer that emits [workflow commands], so that
+/// GitHub Actions shows every label as an annotation of the source file.
+///
+/// [workflow commands]: https://docs.github.com/actions/reference/workflow-commands-for-github-actions
+pub struct GithubActions<W = Stdout> {
+    writer: W,
+}
+
+impl Default for GithubActions {
+    fn default() -> Self {
+        Self::with_writer(std::io::stdout())
+    }
+}
+
+impl<W> GithubActions<W>
+where
+    W: Write,
+{
+    /// Create a new `GithubActions` that renders into `writer`.
+    pub fn with_writer(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Consume this `GithubActions`, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_command<F>(&mut self, files: &F, diagnostic: &Diagnostic) -> Result<()>
+    where
+        F: Files,
+    {
+        let command = match diagnostic.level {
+            Level::Bug | Level::Error => "error",
+            Level::Warning => "warning",
+            Level::Note | Level::Help => "notice",
+        };
+
+        let mut title = diagnostic.message.to_string();
+
+        if let Some(code) = &diagnostic.code {
+            title = format!("[{}] {}", code, title);
+        }
+
+        let notes = diagnostic
+            .nodes
+            .iter()
+            .chain(&diagnostic.helps)
+            .map(|note| format!("\n{}", note))
+            .collect::<String>();
+
+        if diagnostic.labels.is_empty() {
+            return writeln!(
+                &mut self.writer,
+                "::{} title={}::{}",
+                command,
+                escape_property(&title),
+                escape_data(&format!("{}{}", diagnostic.message, notes))
+            );
+        }
+
+        for label in &diagnostic.labels {
+            let location =
+                files.to_location(label.id, &label.primary.byte_range(files, label.id)?)?;
+
+            writeln!(
+                &mut self.writer,
+                "::{} file={},line={},col={},endLine={},endColumn={},title={}::{}",
+                command,
+                escape_property(files.to_file_name(label.id)?),
+                location.start.lines,
+                location.start.cols,
+                location.end.lines,
+                location.end.cols,
+                escape_property(&title),
+                escape_data(&format!("{}{}", label.primary.message, notes))
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn escape_data(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(value: &str) -> String {
+    escape_data(value).replace(':', "%3A").replace(',', "%2C")
+}
+
+impl<W> Renderer for GithubActions<W>
+where
+    W: Write,
+{
+    type Error = std::io::Error;
+
+    fn render<'a, F, D>(&mut self, files: &F, diagnostic: D) -> Result<()>
+    where
+        F: Files,
+        Diagnostic<'a>: From<D>,
+    {
+        let diagnostic: Diagnostic<'a> = diagnostic.into();
+
+        check_files(files, &diagnostic)?;
+
+        self.write_command(files, &diagnostic)?;
+
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Diagnostic, Label, Renderer, SourceCodes};
+
+    use super::GithubActions;
+
+    #[test]
+    fn test_github_actions() {
+        let mut files = SourceCodes::default();
+
+        files.add("src/main.rs", "fn main() {\n    let x = 1\n}\n");
+
+        let mut renderer = GithubActions::with_writer(vec![]);
+
+        renderer
+            .render(
+                &files,
+                Diagnostic::error("expected `;`, found `}`")
+                    .with_code(1)
+                    .with_label(Label::new(0, 16..25, "missing `;`"))
+                    .with_note("statements end with `;`"),
+            )
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(renderer.into_inner()).unwrap(),
+            "::error file=src/main.rs,line=2,col=5,endLine=2,endColumn=14,\
+             title=[000001] expected `;`%2C found `}`::missing `;`%0Astatements end with `;`\n"
+        );
+    }
+}
@@ -0,0 +1,401 @@
+//! A renderer implementation that emits diagnostics as styled HTML `<pre>` blocks.
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    io::{Result, Stdout, Write},
+};
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::{Diagnostic, FileId, LabelRegion, LabelStyle};
+
+use super::{Files, FilesError, Renderer, check_files};
+
+/// The default style sheet of the classes emitted by [`Html`].
+pub const CEPORT_CSS: &str = r#".ceport { font-family: monospace; line-height: 1.3; }
+.ceport .level-bug, .ceport .level-error { color: #d12f1b; font-weight: bold; }
+.ceport .level-warning { color: #b58900; font-weight: bold; }
+.ceport .level-note { color: #2aa198; font-weight: bold; }
+.ceport .level-help { color: #859900; font-weight: bold; }
+.ceport .gutter { color: #268bd2; }
+.ceport .label-primary { color: #d12f1b; }
+.ceport .label-secondary { color: #268bd2; }
+"#;
+
+/// A diagnostic reporting renderer that emits one `<pre class="ceport">` block per diagnostic,
+/// with CSS classes per severity and per label instead of ANSI colors, e.g. for web pages.
+///
+/// Source code and messages are HTML-escaped, see [`CEPORT_CSS`] for the emitted classes.
+pub struct Html<W = Stdout> {
+    writer: W,
+}
+
+impl Default for Html {
+    fn default() -> Self {
+        Self::with_writer(std::io::stdout())
+    }
+}
+
+/// An underline of one region on one source line.
+struct Underline<'a> {
+    start: usize,
+    end: usize,
+    class: &'static str,
+    glyph: char,
+    message: &'a str,
+}
+
+impl<W> Html<W>
+where
+    W: Write,
+{
+    /// Create a new `Html` that renders into `writer`.
+    pub fn with_writer(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Consume this `Html`, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_diagnostic<F>(&mut self, files: &F, diagnostic: &Diagnostic) -> Result<()>
+    where
+        F: Files,
+    {
+        writeln!(&mut self.writer, r#"<pre class="ceport">"#)?;
+
+        let level = diagnostic.level.as_str();
+
+        write!(
+            &mut self.writer,
+            r#"<span class="level-{}">{}"#,
+            level, level
+        )?;
+
+        if let Some(code) = &diagnostic.code {
+            write!(&mut self.writer, "[{}]", escape(&code.to_string()))?;
+        }
+
+        writeln!(&mut self.writer, "</span>: {}", escape(&diagnostic.message))?;
+
+        let mut gutter_width = 1;
+
+        for label in &diagnostic.labels {
+            for region in std::iter::once(&label.primary).chain(&label.secondary) {
+                let location = files.to_location(label.id, &region.byte_range(files, label.id)?)?;
+
+                gutter_width = gutter_width.max(location.end.lines.to_string().len());
+            }
+        }
+
+        for label in &diagnostic.labels {
+            let primary_class = match label.style {
+                LabelStyle::Primary => ("label-primary", '^'),
+                LabelStyle::Secondary => ("label-secondary", '-'),
+            };
+
+            let regions = std::iter::once((&label.primary, primary_class)).chain(
+                label
+                    .secondary
+                    .iter()
+                    .map(|region| (region, ("label-secondary", '-'))),
+            );
+
+            let mut lines = BTreeMap::<usize, Vec<Underline>>::new();
+
+            for (region, (class, glyph)) in regions {
+                add_underlines(files, label.id, region, class, glyph, &mut lines)?;
+            }
+
+            let location =
+                files.to_location(label.id, &label.primary.byte_range(files, label.id)?)?;
+
+            writeln!(
+                &mut self.writer,
+                r#"<span class="gutter">{}--&gt;</span> {}:{}"#,
+                " ".repeat(gutter_width),
+                escape(files.to_file_name(label.id)?),
+                location.start
+            )?;
+
+            self.write_gutter(gutter_width, "")?;
+            writeln!(&mut self.writer)?;
+
+            let mut last = None;
+
+            for (line, mut underlines) in lines {
+                if let Some(last) = last
+                    && line > last + 1
+                {
+                    writeln!(&mut self.writer, r#"<span class="gutter">...</span>"#)?;
+                }
+
+                last = Some(line);
+
+                let content = files.try_line(label.id, line).unwrap_or("");
+
+                self.write_gutter(gutter_width, &line.to_string())?;
+                writeln!(&mut self.writer, " {}", escape(content))?;
+
+                underlines.sort_by_key(|underline| underline.start);
+
+                for underline in underlines {
+                    self.write_gutter(gutter_width, "")?;
+
+                    let padding = before(content, underline.start)
+                        .chars()
+                        .map(|c| match c {
+                            '\t' => "\t".to_owned(),
+                            c => " ".repeat(c.width().unwrap_or(0)),
+                        })
+                        .collect::<String>();
+
+                    let width = content
+                        .get(underline.start - 1..underline.end - 1)
+                        .unwrap_or("")
+                        .chars()
+                        .map(|c| c.width().unwrap_or(0))
+                        .sum::<usize>()
+                        .max(1);
+
+                    write!(
+                        &mut self.writer,
+                        r#" {}<span class="{}">{}"#,
+                        padding,
+                        underline.class,
+                        underline.glyph.to_string().repeat(width)
+                    )?;
+
+                    if !underline.message.is_empty() {
+                        write!(&mut self.writer, " {}", escape(underline.message))?;
+                    }
+
+                    writeln!(&mut self.writer, "</span>")?;
+                }
+            }
+        }
+
+        for label in &diagnostic.labels {
+            for suggestion in &label.suggestions {
+                let range = &suggestion.range;
+                let original = files.source_slice(
+                    label.id,
+                    &(range.start.min(range.end)..range.start.max(range.end)),
+                )?;
+
+                let help = match (original.is_empty(), suggestion.replacement.is_empty()) {
+                    (true, _) => format!("insert `{}`", suggestion.replacement),
+                    (false, true) => format!("remove `{}`", original),
+                    (false, false) => {
+                        format!("replace `{}` with `{}`", original, suggestion.replacement)
+                    }
+                };
+
+                writeln!(
+                    &mut self.writer,
+                    r#"{} <span class="gutter">=</span> help: {}"#,
+                    " ".repeat(gutter_width),
+                    escape(&help)
+                )?;
+            }
+        }
+
+        let notes = diagnostic.nodes.iter().map(|note| ("note", note));
+        let helps = diagnostic.helps.iter().map(|help| ("help", help));
+
+        let entries = notes.chain(helps);
+
+        for (role, entry) in entries {
+            writeln!(
+                &mut self.writer,
+                r#"{} <span class="gutter">=</span> {}: {}"#,
+                " ".repeat(gutter_width),
+                role,
+                escape(entry)
+            )?;
+        }
+
+        writeln!(&mut self.writer, "</pre>")
+    }
+
+    fn write_gutter(&mut self, width: usize, line: &str) -> Result<()> {
+        write!(
+            &mut self.writer,
+            r#"<span class="gutter">{:>width$} |</span>"#,
+            line,
+            width = width
+        )
+    }
+}
+
+/// Adds the underlines of `region` to the lines they are drawn under: the whole region for
+/// a single line region, or the start and end of a multiline region.
+fn add_underlines<'a, F>(
+    files: &F,
+    id: FileId,
+    region: &'a LabelRegion<'_>,
+    class: &'static str,
+    glyph: char,
+    lines: &mut BTreeMap<usize, Vec<Underline<'a>>>,
+) -> std::result::Result<(), FilesError>
+where
+    F: Files,
+{
+    let location = files.to_location(id, &region.byte_range(files, id)?)?;
+
+    if location.start.lines == location.end.lines {
+        lines
+            .entry(location.start.lines)
+            .or_default()
+            .push(Underline {
+                start: location.start.cols,
+                end: location.end.cols,
+                class,
+                glyph,
+                message: &region.message,
+            });
+
+        return Ok(());
+    }
+
+    let first = files.try_line(id, location.start.lines).unwrap_or("");
+
+    lines
+        .entry(location.start.lines)
+        .or_default()
+        .push(Underline {
+            start: location.start.cols,
+            end: first.len() + 1,
+            class,
+            glyph,
+            message: "",
+        });
+
+    lines
+        .entry(location.end.lines)
+        .or_default()
+        .push(Underline {
+            start: 1,
+            end: location.end.cols,
+            class,
+            glyph,
+            message: &region.message,
+        });
+
+    Ok(())
+}
+
+/// Returns the part of `line` before the 1-based byte column `cols`.
+fn before(line: &str, cols: usize) -> &str {
+    line.get(..cols.saturating_sub(1)).unwrap_or(line)
+}
+
+/// Escapes `text` for HTML text content.
+fn escape(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"']) {
+        return Cow::Borrowed(text);
+    }
+
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .into()
+}
+
+impl<W> Renderer for Html<W>
+where
+    W: Write,
+{
+    type Error = std::io::Error;
+
+    fn render<'a, F, D>(&mut self, files: &F, diagnostic: D) -> Result<()>
+    where
+        F: Files,
+        Diagnostic<'a>: From<D>,
+    {
+        self.render_ref(files, &diagnostic.into())
+    }
+
+    fn render_ref<F>(&mut self, files: &F, diagnostic: &Diagnostic<'_>) -> Result<()>
+    where
+        F: Files,
+    {
+        check_files(files, diagnostic)?;
+
+        self.write_diagnostic(files, diagnostic)?;
+
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Diagnostic, Label, Renderer, SourceCodes};
+
+    use super::Html;
+
+    #[test]
+    fn test_html() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("src/main.rs", "fn main() {\n    let x: Vec<u8> = 1;\n}\n");
+
+        let mut html = Html::with_writer(vec![]);
+
+        html.render(
+            &files,
+            Diagnostic::error("mismatched types")
+                .with_code("E0308")
+                .with_label(
+                    Label::new(id, 33..34, "expected `Vec<u8>`")
+                        .with_secondary(23..30, "expected due to this")
+                        .with_suggestion(33..34, "vec![1]"),
+                )
+                .with_note("a & b"),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(html.into_inner()).unwrap();
+
+        let golden = [
+            r#"<pre class="ceport">"#,
+            r#"<span class="level-error">error[E0308]</span>: mismatched types"#,
+            r#"<span class="gutter"> --&gt;</span> src/main.rs:2:22"#,
+            r#"<span class="gutter">  |</span>"#,
+            r#"<span class="gutter">2 |</span>     let x: Vec&lt;u8&gt; = 1;"#,
+            r#"<span class="gutter">  |</span>            <span class="label-secondary">------- expected due to this</span>"#,
+            r#"<span class="gutter">  |</span>                      <span class="label-primary">^ expected `Vec&lt;u8&gt;`</span>"#,
+            r#"  <span class="gutter">=</span> help: replace `1` with `vec![1]`"#,
+            r#"  <span class="gutter">=</span> note: a &amp; b"#,
+            "</pre>",
+            "",
+        ];
+
+        assert_eq!(output, golden.join("\n"));
+    }
+
+    #[test]
+    fn test_html_multiline() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("a.txt", "one\ntwo\nthree\nfour\n");
+
+        let mut html = Html::with_writer(vec![]);
+
+        html.render(
+            &files,
+            Diagnostic::warning("odd block").with_label(Label::new(id, 1..16, "here")),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(html.into_inner()).unwrap();
+
+        assert!(output.contains(
+            "<span class=\"gutter\">1 |</span> one\n<span class=\"gutter\">  |</span>  <span class=\"label-primary\">^^</span>\n<span class=\"gutter\">...</span>\n<span class=\"gutter\">4 |</span> four\n"
+        ));
+        assert!(output.contains("<span class=\"label-primary\">^^ here</span>\n"));
+    }
+}
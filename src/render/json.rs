@@ -0,0 +1,349 @@
+//! A renderer implementation that emits one JSON object per diagnostic (JSONL).
+
+use std::{
+    borrow::Cow,
+    io::{Result, Stdout, Write},
+    ops::Range,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Diagnostic, FileId, Label, LabelRegion, LabelStyle, Location, Suggestion};
+
+use super::{Files, FilesError, Renderer, check_files};
+
+/// One line of the output of [`Json`].
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct DiagnosticRecord<'r> {
+    /// `bug`, `error`, `warning`, `note` or `help`.
+    pub level: Cow<'r, str>,
+    /// The optional diagnostic code, as displayed, e.g. `000308` or `E0308`.
+    pub code: Option<Cow<'r, str>>,
+    /// The main message.
+    pub message: Cow<'r, str>,
+    /// The notes of the diagnostic.
+    pub notes: Vec<Cow<'r, str>>,
+    /// The help lines of the diagnostic.
+    pub helps: Vec<Cow<'r, str>>,
+    /// Every labeled region, primary regions of a label first.
+    pub labels: Vec<LabelRecord<'r>>,
+    /// A link to the explanation of the code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<Cow<'r, str>>,
+    /// The stage that reports the diagnostic, as displayed, e.g. `parsing SVG`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stage: Option<Cow<'r, str>>,
+    /// Extra structured key/value data, in insertion order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub metadata: Vec<(Cow<'r, str>, Cow<'r, str>)>,
+}
+
+/// One labeled region of a [`DiagnosticRecord`].
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct LabelRecord<'r> {
+    /// The name of the file.
+    pub file_name: Cow<'r, str>,
+    /// The byte range of the region.
+    pub range: Range<usize>,
+    /// The location of the start of the region.
+    pub start: Position,
+    /// The location of the end of the region (exclusive).
+    pub end: Position,
+    /// The message of the region.
+    pub message: Cow<'r, str>,
+    /// Whether this is the primary region of a primary-styled label.
+    pub primary: bool,
+    /// The suggested replacements of the label, on the record of its primary region only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub suggestions: Vec<Suggestion<'r>>,
+}
+
+/// A 1-based line and (byte) column.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct Position {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based (byte) column.
+    pub column: usize,
+}
+
+impl From<Location> for Position {
+    fn from(value: Location) -> Self {
+        Self {
+            line: value.lines,
+            column: value.cols,
+        }
+    }
+}
+
+/// A diagnostic reporting renderer that emits one [`DiagnosticRecord`] JSON object per line,
+/// for editors and CI tools that consume JSON.
+pub struct Json<W = Stdout> {
+    writer: W,
+}
+
+impl Default for Json {
+    fn default() -> Self {
+        Self::with_writer(std::io::stdout())
+    }
+}
+
+impl<W> Json<W>
+where
+    W: Write,
+{
+    /// Create a new `Json` that renders into `writer`.
+    pub fn with_writer(writer: W) -> Self {
+        Self { writer }
+    }
+
+    /// Consume this `Json`, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Convert `diagnostic` into a [`DiagnosticRecord`], resolving its regions with `files`.
+pub fn to_record<'r, F>(
+    files: &'r F,
+    diagnostic: &'r Diagnostic<'_>,
+) -> std::result::Result<DiagnosticRecord<'r>, FilesError>
+where
+    F: Files,
+{
+    let mut labels = vec![];
+
+    for label in &diagnostic.labels {
+        let mut record = to_label_record(
+            files,
+            label.id,
+            &label.primary,
+            label.style == LabelStyle::Primary,
+        )?;
+
+        record.suggestions = to_suggestions(label);
+
+        labels.push(record);
+
+        for region in &label.secondary {
+            labels.push(to_label_record(files, label.id, region, false)?);
+        }
+    }
+
+    Ok(DiagnosticRecord {
+        level: diagnostic.level.as_str().into(),
+        code: diagnostic.code.as_ref().map(|code| code.to_string().into()),
+        message: Cow::Borrowed(&diagnostic.message),
+        notes: diagnostic
+            .nodes
+            .iter()
+            .map(|note| Cow::Borrowed(note.as_ref()))
+            .collect(),
+        helps: diagnostic
+            .helps
+            .iter()
+            .map(|help| Cow::Borrowed(help.as_ref()))
+            .collect(),
+        labels,
+        url: diagnostic.url.as_deref().map(Cow::Borrowed),
+        stage: diagnostic.stage.map(|stage| stage.to_string().into()),
+        metadata: diagnostic
+            .metadata
+            .iter()
+            .map(|(key, value)| (Cow::Borrowed(key.as_ref()), Cow::Borrowed(value.as_ref())))
+            .collect(),
+    })
+}
+
+fn to_label_record<'r, F>(
+    files: &'r F,
+    id: FileId,
+    region: &'r LabelRegion<'_>,
+    primary: bool,
+) -> std::result::Result<LabelRecord<'r>, FilesError>
+where
+    F: Files,
+{
+    let range = region.byte_range(files, id)?;
+    let location = files.to_location(id, &range)?;
+
+    Ok(LabelRecord {
+        file_name: files.to_file_name(id)?.into(),
+        range,
+        start: location.start.into(),
+        end: location.end.into(),
+        message: Cow::Borrowed(&region.message),
+        primary,
+        suggestions: vec![],
+    })
+}
+
+fn to_suggestions<'r>(label: &'r Label<'_>) -> Vec<Suggestion<'r>> {
+    label
+        .suggestions
+        .iter()
+        .map(|suggestion| Suggestion {
+            range: suggestion.range.clone(),
+            replacement: Cow::Borrowed(&suggestion.replacement),
+            applicability: suggestion.applicability,
+        })
+        .collect()
+}
+
+impl<W> Renderer for Json<W>
+where
+    W: Write,
+{
+    type Error = std::io::Error;
+
+    fn render<'a, F, D>(&mut self, files: &F, diagnostic: D) -> Result<()>
+    where
+        F: Files,
+        Diagnostic<'a>: From<D>,
+    {
+        self.render_ref(files, &diagnostic.into())
+    }
+
+    fn render_ref<F>(&mut self, files: &F, diagnostic: &Diagnostic<'_>) -> Result<()>
+    where
+        F: Files,
+    {
+        check_files(files, diagnostic)?;
+
+        let record = to_record(files, diagnostic)?;
+
+        serde_json::to_writer(&mut self.writer, &record)?;
+
+        writeln!(&mut self.writer)?;
+
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Applicability, Diagnostic, Label, Renderer, SourceCodes, Stage, Suggestion};
+
+    use super::{DiagnosticRecord, Json, Position};
+
+    #[test]
+    fn test_json() {
+        let mut files = SourceCodes::default();
+
+        files.add("src/main.rs", "fn main() {\n    let x = 1\n}\n");
+
+        let mut renderer = Json::with_writer(vec![]);
+
+        renderer
+            .render_all(
+                &files,
+                [
+                    Diagnostic::error("expected `;`")
+                        .with_code("E0308")
+                        .with_code_url("https://example.com/E0308")
+                        .with_stage(Stage::Parsing("Rust"))
+                        .with_label(
+                            Label::new(0, 16..25, "missing `;`")
+                                .with_secondary(26..27, "before this")
+                                .with_applicable_suggestion(
+                                    25..25,
+                                    ";",
+                                    Applicability::MachineApplicable,
+                                ),
+                        )
+                        .with_note("statements end with `;`")
+                        .with_metadata("category", "syntax"),
+                    Diagnostic::warning("unused variable"),
+                ],
+            )
+            .unwrap();
+
+        let output = String::from_utf8(renderer.into_inner()).unwrap();
+
+        let records = output
+            .lines()
+            .map(|line| serde_json::from_str::<DiagnosticRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(records.len(), 2);
+
+        let record = &records[0];
+
+        assert_eq!(record.level, "error");
+        assert_eq!(record.code.as_deref(), Some("E0308"));
+        assert_eq!(record.message, "expected `;`");
+        assert_eq!(record.notes, ["statements end with `;`"]);
+        assert_eq!(record.url.as_deref(), Some("https://example.com/E0308"));
+        assert_eq!(record.stage.as_deref(), Some("parsing Rust"));
+        assert_eq!(record.metadata, [("category".into(), "syntax".into())]);
+        assert_eq!(record.labels.len(), 2);
+
+        let label = &record.labels[0];
+
+        assert_eq!(label.file_name, "src/main.rs");
+        assert_eq!(label.range, 16..25);
+        assert_eq!(label.start, Position { line: 2, column: 5 });
+        assert_eq!(
+            label.end,
+            Position {
+                line: 2,
+                column: 14
+            }
+        );
+        assert_eq!(label.message, "missing `;`");
+        assert!(label.primary);
+        assert_eq!(
+            label.suggestions,
+            [Suggestion {
+                range: 25..25,
+                replacement: ";".into(),
+                applicability: Applicability::MachineApplicable,
+            }]
+        );
+
+        assert_eq!(record.labels[1].start, Position { line: 3, column: 1 });
+        assert!(!record.labels[1].primary);
+        assert!(record.labels[1].suggestions.is_empty());
+
+        assert_eq!(records[1].level, "warning");
+        assert_eq!(records[1].code, None);
+        assert!(records[1].labels.is_empty());
+        assert_eq!(records[1].url, None);
+        assert_eq!(records[1].stage, None);
+
+        // empty fields are omitted.
+        let second = output.lines().nth(1).unwrap();
+
+        assert!(!second.contains("url"));
+        assert!(!second.contains("stage"));
+        assert!(!second.contains("metadata"));
+        assert!(output.contains(r#""applicability":"MachineApplicable""#));
+
+        // records round-trip through their JSON form.
+        assert_eq!(
+            serde_json::from_str::<DiagnosticRecord>(&serde_json::to_string(record).unwrap())
+                .unwrap(),
+            *record
+        );
+    }
+
+    #[test]
+    fn test_hidden_code() {
+        let files = SourceCodes::default();
+
+        let mut renderer = Json::with_writer(vec![]);
+
+        renderer
+            .render(&files, Diagnostic::error("mismatched types").with_code(308))
+            .unwrap();
+
+        let output = String::from_utf8(renderer.into_inner()).unwrap();
+
+        let record = serde_json::from_str::<DiagnosticRecord>(&output).unwrap();
+
+        // hiding the code is a terminal option, machine output always carries it.
+        assert_eq!(record.code.as_deref(), Some("000308"));
+        assert_eq!(record.url, None);
+        assert!(!output.contains("url"));
+    }
+}
@@ -0,0 +1,1551 @@
+//! The layout of diagnostics shared by the [`Term`](super::term::Term) and
+//! [`Plain`](super::plain::Plain) renderers: headers, gutters, underlines and notes.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    io::{Result, Write},
+    ops::Range,
+    path::Path,
+};
+
+use termcolor::{ColorSpec, HyperlinkSpec, WriteColor};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{Code, Diagnostic, FileId, Label, LabelRegion, LabelStyle, Level};
+
+use super::{
+    CodeExplanations, Files, FilesError, Location, Renderer, Theme, check_file, check_files,
+};
+
+/// Multiline regions spanning more lines than this are rendered in compact form by default.
+const DEFAULT_MULTILINE_THRESHOLD: usize = 10;
+
+/// Distance between uniform tab stops.
+const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// Display columns that tabs expand to.
+#[derive(Debug, Clone)]
+struct TabStops {
+    /// Explicit stops, in ascending order.
+    stops: Vec<usize>,
+    /// Distance between the uniform stops past the last explicit one.
+    width: usize,
+}
+
+impl Default for TabStops {
+    fn default() -> Self {
+        Self {
+            stops: vec![],
+            width: DEFAULT_TAB_WIDTH,
+        }
+    }
+}
+
+impl TabStops {
+    /// Returns the column of the tab stop after `column`.
+    ///
+    /// Past the last configured stop, stops continue uniformly every `width` columns.
+    fn next(&self, column: usize) -> usize {
+        if let Some(stop) = self.stops.iter().find(|stop| **stop > column) {
+            return *stop;
+        }
+
+        let last = self.stops.last().copied().unwrap_or(0);
+
+        column + self.width - (column - last) % self.width
+    }
+
+    /// Returns the display width of `text`, with tabs expanded.
+    fn width(&self, text: &str) -> usize {
+        text.chars().fold(0, |column, c| {
+            if c == '\t' {
+                self.next(column)
+            } else {
+                column + c.width().unwrap_or(0)
+            }
+        })
+    }
+
+    /// Returns `text` with tabs replaced by spaces up to the next stop.
+    fn expand(&self, text: &str) -> String {
+        let mut expanded = String::with_capacity(text.len());
+        let mut column = 0;
+
+        for c in text.chars() {
+            if c == '\t' {
+                let next = self.next(column);
+                expanded.extend(std::iter::repeat_n(' ', next - column));
+                column = next;
+            } else {
+                expanded.push(c);
+                column += c.width().unwrap_or(0);
+            }
+        }
+
+        expanded
+    }
+}
+
+/// How file names are shown in the header of a file block.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum PathStyle {
+    /// Show the file name as returned by [`Files::to_file_name`].
+    #[default]
+    Full,
+    /// Show only the last component of the path, e.g. `main.rs`.
+    FileNameOnly,
+    /// Show the path relative to the current working directory, if it is inside of it.
+    RelativeToCwd,
+}
+
+impl PathStyle {
+    /// Returns the file name `name` shown in this style.
+    pub fn apply<'n>(&self, name: &'n str) -> Cow<'n, str> {
+        match self {
+            PathStyle::Full => Cow::Borrowed(name),
+            PathStyle::FileNameOnly => Path::new(name)
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy())
+                .unwrap_or(Cow::Borrowed(name)),
+            PathStyle::RelativeToCwd => match std::env::current_dir() {
+                Ok(cwd) => relative_to(name, &cwd),
+                Err(_) => Cow::Borrowed(name),
+            },
+        }
+    }
+}
+
+/// Returns `name` relative to `base`, or `name` itself if it is not inside of `base`.
+fn relative_to<'n>(name: &'n str, base: &Path) -> Cow<'n, str> {
+    match Path::new(name).strip_prefix(base) {
+        Ok(relative) => relative.to_string_lossy(),
+        Err(_) => Cow::Borrowed(name),
+    }
+}
+
+/// How much detail [`Term`](super::term::Term) and [`Plain`](super::plain::Plain) render for
+/// each diagnostic.
+#[derive(Debug, Default, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Verbosity {
+    /// Only the header and the primary regions of labels.
+    Quiet,
+    /// The header, all regions of labels and the notes.
+    #[default]
+    Normal,
+    /// Everything of `Normal`, plus the metadata and the documentation link.
+    Verbose,
+}
+
+/// The characters the gutter and the multiline connectors are drawn with.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum CharSet {
+    /// Unicode box-drawing characters, e.g. `┌─` and `│`.
+    #[default]
+    Unicode,
+    /// Plain ASCII, for consoles that can not display box-drawing characters.
+    Ascii,
+}
+
+impl CharSet {
+    /// The corner in front of the file name of a file block.
+    fn corner(self) -> &'static str {
+        match self {
+            CharSet::Unicode => "┌─",
+            CharSet::Ascii => "+-",
+        }
+    }
+
+    /// The gutter bar and the channel of a multiline region.
+    fn vertical(self) -> char {
+        match self {
+            CharSet::Unicode => '│',
+            CharSet::Ascii => '|',
+        }
+    }
+
+    /// The gutter bar of a row standing in for skipped lines.
+    fn gap(self) -> char {
+        match self {
+            CharSet::Unicode => '┆',
+            CharSet::Ascii => ':',
+        }
+    }
+}
+
+/// A resolved region to underline in a file block.
+struct Region<'r> {
+    location: Range<Location>,
+    message: &'r str,
+    primary: bool,
+    level: Level,
+    glyph: Option<char>,
+}
+
+/// The output of a [`Layout`]: text, styled where the underlying writer supports it.
+pub(crate) trait Sink: Write {
+    /// Set the color of the following output.
+    fn set_color(&mut self, spec: &ColorSpec) -> Result<()>;
+
+    /// Returns true if [`set_hyperlink`](Self::set_hyperlink) has any effect.
+    fn supports_hyperlinks(&self) -> bool;
+
+    /// Open or close a hyperlink around the following output.
+    fn set_hyperlink(&mut self, link: &HyperlinkSpec) -> Result<()>;
+}
+
+/// A [`Sink`] writing colors and hyperlinks into a [`WriteColor`].
+pub(crate) struct Colored<W> {
+    writer: W,
+    color_failed: bool,
+}
+
+impl<W> Colored<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self {
+            writer,
+            color_failed: false,
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W> Write for Colored<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W> Sink for Colored<W>
+where
+    W: WriteColor,
+{
+    /// On the first failure, the remainder is rendered without color instead of aborting.
+    fn set_color(&mut self, spec: &ColorSpec) -> Result<()> {
+        if self.color_failed {
+            return Ok(());
+        }
+
+        if let Err(err) = self.writer.set_color(spec) {
+            log::warn!("set color failed: {}, fall back to plain output.", err);
+            self.color_failed = true;
+        }
+
+        Ok(())
+    }
+
+    fn supports_hyperlinks(&self) -> bool {
+        self.writer.supports_hyperlinks()
+    }
+
+    fn set_hyperlink(&mut self, link: &HyperlinkSpec) -> Result<()> {
+        self.writer.set_hyperlink(link)
+    }
+}
+
+/// A [`Sink`] writing only the text into any [`Write`], dropping colors and hyperlinks.
+pub(crate) struct Uncolored<W>(W);
+
+impl<W> Uncolored<W> {
+    pub(crate) fn new(writer: W) -> Self {
+        Self(writer)
+    }
+
+    pub(crate) fn into_inner(self) -> W {
+        self.0
+    }
+}
+
+impl<W> Write for Uncolored<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W> Sink for Uncolored<W>
+where
+    W: Write,
+{
+    fn set_color(&mut self, _spec: &ColorSpec) -> Result<()> {
+        Ok(())
+    }
+
+    fn supports_hyperlinks(&self) -> bool {
+        false
+    }
+
+    fn set_hyperlink(&mut self, _link: &HyperlinkSpec) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The options and the snippet algorithm of the terminal layout, writing into a [`Sink`].
+///
+/// The fields are set by the builders of the renderers driving it.
+pub(crate) struct Layout<S> {
+    pub(crate) writer: S,
+    pub(crate) theme: Theme,
+    pub(crate) multiline_threshold: usize,
+    pub(crate) coalesce: bool,
+    pub(crate) min_gutter_width: usize,
+    pub(crate) dedup_notes: bool,
+    tab_stops: TabStops,
+    pub(crate) bidi_isolation: bool,
+    pub(crate) strict: bool,
+    pub(crate) path_style: PathStyle,
+    pub(crate) locator: bool,
+    pub(crate) verbosity: Verbosity,
+    pub(crate) explanations: Option<CodeExplanations>,
+    pub(crate) code_display: bool,
+    pub(crate) stage_display: bool,
+    pub(crate) charset: CharSet,
+    pub(crate) width: Option<usize>,
+    pub(crate) context: usize,
+}
+
+impl<S> Layout<S>
+where
+    S: Sink,
+{
+    /// Create a new `Layout` with the default options that writes into `writer`.
+    pub(crate) fn with_writer(writer: S) -> Self {
+        Self {
+            writer,
+            theme: Theme::default(),
+            multiline_threshold: DEFAULT_MULTILINE_THRESHOLD,
+            coalesce: false,
+            min_gutter_width: 0,
+            dedup_notes: true,
+            tab_stops: TabStops::default(),
+            bidi_isolation: false,
+            strict: false,
+            path_style: PathStyle::Full,
+            locator: true,
+            verbosity: Verbosity::Normal,
+            explanations: None,
+            code_display: true,
+            stage_display: false,
+            charset: CharSet::Unicode,
+            width: None,
+            context: 0,
+        }
+    }
+
+    /// Consume this `Layout`, returning the underlying sink.
+    pub(crate) fn into_inner(self) -> S {
+        self.writer
+    }
+
+    /// Set the explicit tab stops, in any order.
+    pub(crate) fn set_tab_stops<I>(&mut self, stops: I)
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let mut stops = stops.into_iter().collect::<Vec<_>>();
+        stops.sort_unstable();
+        stops.dedup();
+
+        self.tab_stops.stops = stops;
+    }
+
+    /// Set the distance between uniform tab stops, treating 0 as 1.
+    pub(crate) fn set_tab_width(&mut self, width: usize) {
+        self.tab_stops.width = width.max(1);
+    }
+
+    /// Set the color of the following output, if the sink supports color.
+    fn set_color(&mut self, spec: &ColorSpec) -> Result<()> {
+        self.writer.set_color(spec)
+    }
+
+    fn text_color(&mut self) -> Result<()> {
+        let spec = self.theme.text_color.clone();
+        self.set_color(&spec)
+    }
+
+    fn suggestion_color(&mut self) -> Result<()> {
+        let spec = self.theme.suggestion_color.clone();
+        self.set_color(&spec)
+    }
+
+    fn label_color(&mut self) -> Result<()> {
+        let spec = self.theme.label_color.clone();
+        self.set_color(&spec)
+    }
+
+    fn code_color(&mut self) -> Result<()> {
+        let spec = self.theme.code_color.clone();
+        self.set_color(&spec)
+    }
+
+    fn underline_color(&mut self, level: Level) -> Result<()> {
+        let spec = self.theme.underline_color(level);
+        self.set_color(&spec)
+    }
+
+    fn write_level(&mut self, level: Level) -> Result<()> {
+        self.set_color(
+            ColorSpec::new()
+                .set_bold(true)
+                .set_fg(Some(self.theme.level_color(level))),
+        )?;
+
+        write!(&mut self.writer, "{}", level.label())
+    }
+
+    /// Writes `[code]`, as an OSC 8 hyperlink to `url` if the writer supports hyperlinks.
+    fn write_code(&mut self, code: &Code, url: Option<&str>) -> Result<()> {
+        let Some(url) = url.filter(|_| self.writer.supports_hyperlinks()) else {
+            return write!(&mut self.writer, "[{}]", code);
+        };
+
+        self.writer
+            .set_hyperlink(&HyperlinkSpec::open(url.as_bytes()))?;
+        write!(&mut self.writer, "[{}]", code)?;
+        self.writer.set_hyperlink(&HyperlinkSpec::close())
+    }
+
+    fn write_header(&mut self, diagnostic: &Diagnostic) -> Result<()> {
+        self.write_level(diagnostic.level)?;
+
+        if let Some(code) = &diagnostic.code
+            && self.code_display
+        {
+            self.write_code(code, diagnostic.url.as_deref())?;
+        }
+
+        if let Some(stage) = &diagnostic.stage
+            && self.stage_display
+        {
+            write!(&mut self.writer, " ({})", stage)?;
+        }
+
+        self.text_color()?;
+
+        writeln!(
+            &mut self.writer,
+            "{}{}",
+            self.theme.header_separator, diagnostic.message
+        )?;
+
+        Ok(())
+    }
+
+    /// Writes the ` --> file:line:col` line of the first primary-styled label of `diagnostic`.
+    fn write_locator<F>(&mut self, files: &F, diagnostic: &Diagnostic) -> Result<()>
+    where
+        F: Files,
+    {
+        if !self.locator {
+            return Ok(());
+        }
+
+        // the first in source order, so the output does not depend on insertion order.
+        let Some(label) = diagnostic
+            .labels
+            .iter()
+            .filter(|label| label.style == LabelStyle::Primary)
+            .min_by_key(|label| {
+                let range = label.primary.byte_range(files, label.id);
+
+                (label.id, range.ok().map(|range| range.start))
+            })
+            .or(diagnostic.labels.first())
+        else {
+            return Ok(());
+        };
+
+        let location = to_location(files, label.id, &label.primary.byte_range(files, label.id)?)?;
+
+        self.label_color()?;
+        write!(&mut self.writer, " --> ")?;
+        self.text_color()?;
+        writeln!(
+            &mut self.writer,
+            "{}:{}",
+            self.path_style.apply(files.to_file_name(label.id)?),
+            location.start
+        )?;
+
+        Ok(())
+    }
+
+    /// Writes a `help` line with the proposed edit of each suggestion, not in quiet mode.
+    fn write_suggestions<F>(
+        &mut self,
+        files: &F,
+        prefix_width: usize,
+        diagnostic: &Diagnostic,
+    ) -> Result<()>
+    where
+        F: Files,
+    {
+        if self.verbosity == Verbosity::Quiet {
+            return Ok(());
+        }
+
+        for label in &diagnostic.labels {
+            for suggestion in &label.suggestions {
+                let original =
+                    files.source_slice(label.id, &to_range(files, label.id, &suggestion.range)?)?;
+
+                self.label_color()?;
+                write!(&mut self.writer, "{} = ", " ".repeat(prefix_width))?;
+                self.code_color()?;
+
+                match (original.is_empty(), suggestion.replacement.is_empty()) {
+                    (true, _) => write!(&mut self.writer, "help: insert `")?,
+                    (false, true) => {
+                        writeln!(&mut self.writer, "help: remove `{}`", original)?;
+                        continue;
+                    }
+                    (false, false) => {
+                        write!(&mut self.writer, "help: replace `{}` with `", original)?
+                    }
+                }
+
+                self.suggestion_color()?;
+                write!(&mut self.writer, "{}", suggestion.replacement)?;
+                self.code_color()?;
+                writeln!(&mut self.writer, "`")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes one `= note: ...` or `= help: ...` entry, with the role in the color of `level`.
+    fn write_note(&mut self, prefix_width: usize, level: Level, entry: &str) -> Result<()> {
+        self.label_color()?;
+        write!(&mut self.writer, "{} = ", " ".repeat(prefix_width))?;
+
+        self.set_color(
+            ColorSpec::new()
+                .set_bold(true)
+                .set_fg(Some(self.theme.level_color(level))),
+        )?;
+        write!(&mut self.writer, "{}:", level.as_str())?;
+        self.code_color()?;
+
+        let indent = prefix_width + 3 + level.as_str().len() + 2;
+
+        // lines of an entry are wrapped on their own, continuing after the role.
+        for (index, line) in entry.split('\n').enumerate() {
+            let column = if index == 0 { indent } else { 0 };
+
+            let parts = self.wrap(line, column, indent);
+
+            if index == 0 {
+                write!(&mut self.writer, " ")?;
+            } else {
+                writeln!(&mut self.writer)?;
+            }
+
+            write!(&mut self.writer, "{}", parts[0])?;
+
+            for part in &parts[1..] {
+                write!(&mut self.writer, "\n{}{}", " ".repeat(indent), part)?;
+            }
+        }
+
+        writeln!(&mut self.writer)
+    }
+
+    fn write_notes(&mut self, prefix_width: usize, diagnostic: &Diagnostic) -> Result<()> {
+        if self.verbosity == Verbosity::Quiet {
+            return Ok(());
+        }
+
+        for (level, entries) in [
+            (Level::Note, &diagnostic.nodes),
+            (Level::Help, &diagnostic.helps),
+        ] {
+            let mut rendered = HashSet::new();
+
+            for entry in entries {
+                if self.dedup_notes && !rendered.insert(entry) {
+                    continue;
+                }
+
+                self.write_note(prefix_width, level, entry)?;
+            }
+        }
+
+        if let Some(code) = &diagnostic.code
+            && let Some(explanations) = &self.explanations
+            && explanations.explain::<Code>(code.clone()).is_some()
+        {
+            let hint = format!(
+                "run `{} {}` for details",
+                explanations.command(),
+                code.name()
+            );
+
+            self.label_color()?;
+            write!(&mut self.writer, "{} =", " ".repeat(prefix_width))?;
+            self.code_color()?;
+            writeln!(&mut self.writer, " {}", hint)?;
+        }
+
+        if self.verbosity < Verbosity::Verbose {
+            return Ok(());
+        }
+
+        for (key, value) in &diagnostic.metadata {
+            self.label_color()?;
+            write!(&mut self.writer, "{} =", " ".repeat(prefix_width))?;
+            self.code_color()?;
+            writeln!(&mut self.writer, " {}: {}", key, value)?;
+        }
+
+        if let Some(url) = &diagnostic.url {
+            self.label_color()?;
+            write!(&mut self.writer, "{} =", " ".repeat(prefix_width))?;
+            self.code_color()?;
+            writeln!(&mut self.writer, " for more information, see {}", url)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_snippets<F>(&mut self, files: &F, diagnostic: &Diagnostic) -> Result<()>
+    where
+        F: Files,
+    {
+        if !diagnostic.has_labels() {
+            self.write_notes(self.min_gutter_width, diagnostic)?;
+            return self.writer.flush();
+        }
+
+        let mut labels = diagnostic.labels.iter().collect::<Vec<_>>();
+
+        labels.sort_by_key(|label| {
+            let range = label.primary.byte_range(files, label.id);
+
+            (label.id, range.ok().map(|range| (range.start, range.end)))
+        });
+
+        let mut prefix_width = self.min_gutter_width;
+
+        // labels of the same file are rendered together, under one file header.
+        for (index, group) in labels.chunk_by(|lhs, rhs| lhs.id == rhs.id).enumerate() {
+            // the last block is flushed together with the notes.
+            if index > 0 {
+                self.writer.flush()?;
+            }
+
+            let mut regions = vec![];
+            let mut modified_lines = HashSet::new();
+
+            for label in group {
+                regions.extend(label_regions(
+                    files,
+                    diagnostic.level,
+                    label,
+                    self.verbosity,
+                )?);
+                modified_lines.extend(suggested_lines(files, label)?);
+            }
+
+            prefix_width = prefix_width.max(self.write_file_snippet(
+                files,
+                group[0].id,
+                regions,
+                &modified_lines,
+            )?);
+        }
+
+        // notes describe the whole diagnostic, so they are written once, after the last block,
+        // aligned under the widest gutter.
+        self.write_suggestions(files, prefix_width, diagnostic)?;
+        self.write_notes(prefix_width, diagnostic)?;
+        self.writer.flush()
+    }
+
+    /// Render a borrowed diagnostic.
+    pub(crate) fn write_diagnostic<F>(&mut self, files: &F, diagnostic: &Diagnostic) -> Result<()>
+    where
+        F: Files,
+    {
+        if self.strict
+            && let Err(err) = diagnostic.validate(files)
+        {
+            return self.write_diagnostic(files, &err.to_diagnostic());
+        }
+
+        check_files(files, diagnostic)?;
+
+        // flush once per logical block, so long outputs show up progressively
+        // and a broken pipe is reported early.
+        self.write_header(diagnostic)?;
+        self.write_locator(files, diagnostic)?;
+        self.writer.flush()?;
+
+        self.write_snippets(files, diagnostic)?;
+
+        Ok(())
+    }
+
+    /// Render the long-form explanation of `code` from the registry set by
+    /// [`explanations`](Self::explanations).
+    ///
+    /// Markdown headings are rendered in the header style and fenced code blocks are indented.
+    /// Returns false, rendering nothing, if `code` has no registered explanation.
+    pub(crate) fn render_explanation<'c, C>(&mut self, code: C) -> Result<bool>
+    where
+        Code<'c>: From<C>,
+    {
+        let code = Code::from(code);
+
+        let Some(text) = self
+            .explanations
+            .as_ref()
+            .and_then(|explanations| explanations.explain::<Code>(code.clone()))
+            .map(str::to_owned)
+        else {
+            return Ok(false);
+        };
+
+        self.text_color()?;
+        self.write_code(&code, None)?;
+        writeln!(&mut self.writer)?;
+
+        let mut fenced = false;
+
+        for line in text.lines() {
+            if line.trim_start().starts_with("```") {
+                fenced = !fenced;
+                continue;
+            }
+
+            if fenced {
+                self.code_color()?;
+                writeln!(&mut self.writer, "    {}", line)?;
+            } else if let Some(heading) = line.strip_prefix('#') {
+                self.text_color()?;
+                writeln!(
+                    &mut self.writer,
+                    "{}",
+                    heading.trim_start_matches('#').trim()
+                )?;
+            } else {
+                self.code_color()?;
+                writeln!(&mut self.writer, "{}", line)?;
+            }
+        }
+
+        self.writer.flush()?;
+
+        Ok(true)
+    }
+
+    /// Render a single label standalone: its file block and underlines, without the diagnostic
+    /// header and notes. The primary region is styled as an [`Error`](Level::Error).
+    pub(crate) fn render_label<F>(&mut self, files: &F, label: &Label<'_>) -> Result<()>
+    where
+        F: Files,
+    {
+        check_file(files, label.id)?;
+
+        self.write_file_snippet(
+            files,
+            label.id,
+            label_regions(files, Level::Error, label, self.verbosity)?,
+            &suggested_lines(files, label)?,
+        )?;
+
+        self.writer.flush()
+    }
+
+    /// Render diagnostics whose labels are all in the file `id` as one merged file block.
+    fn write_coalesced<F>(&mut self, files: &F, id: FileId, group: &[&Diagnostic]) -> Result<()>
+    where
+        F: Files,
+    {
+        let mut regions = vec![];
+        let mut modified_lines = HashSet::new();
+
+        for diagnostic in group {
+            self.write_header(diagnostic)?;
+            self.write_locator(files, diagnostic)?;
+
+            for label in &diagnostic.labels {
+                regions.extend(label_regions(
+                    files,
+                    diagnostic.level,
+                    label,
+                    self.verbosity,
+                )?);
+                modified_lines.extend(suggested_lines(files, label)?);
+            }
+        }
+
+        self.writer.flush()?;
+
+        let prefix_width = self.write_file_snippet(files, id, regions, &modified_lines)?;
+
+        for diagnostic in group {
+            self.write_suggestions(files, prefix_width, diagnostic)?;
+            self.write_notes(prefix_width, diagnostic)?;
+        }
+
+        self.writer.flush()
+    }
+
+    /// Split `text` into the lines of its wrapped form, the first one starting at
+    /// column `first` and the others at column `rest`.
+    fn wrap<'t>(&self, text: &'t str, first: usize, rest: usize) -> Vec<&'t str> {
+        match self.width {
+            Some(width) => wrap_text(
+                text,
+                width.saturating_sub(first),
+                width.saturating_sub(rest),
+            ),
+            None => vec![text],
+        }
+    }
+
+    /// Writes the one-column underline that marks a boundary character of a multiline region.
+    fn write_boundary(&mut self, region: &Region<'_>) -> Result<()> {
+        let underline = self.theme.underline(region.level);
+
+        if region.primary {
+            self.underline_color(region.level)?;
+            write!(
+                &mut self.writer,
+                "{}",
+                region.glyph.unwrap_or(underline.primary)
+            )?;
+        } else {
+            write!(
+                &mut self.writer,
+                "{}",
+                region.glyph.unwrap_or(underline.secondary)
+            )?;
+        }
+
+        self.label_color()
+    }
+
+    fn write_file_snippet<F>(
+        &mut self,
+        files: &F,
+        id: FileId,
+        mut regions: Vec<Region<'_>>,
+        modified_lines: &HashSet<usize>,
+    ) -> Result<usize>
+    where
+        F: Files,
+    {
+        // process regions in source order, so the output does not depend on insertion order.
+        regions.sort_by(|lhs, rhs| {
+            let (lhs_location, rhs_location) = (&lhs.location, &rhs.location);
+
+            (
+                lhs_location.start.lines,
+                lhs_location.start.cols,
+                lhs_location.end.lines,
+                lhs_location.end.cols,
+            )
+                .cmp(&(
+                    rhs_location.start.lines,
+                    rhs_location.start.cols,
+                    rhs_location.end.lines,
+                    rhs_location.end.cols,
+                ))
+                .then(rhs.primary.cmp(&lhs.primary))
+                .then(lhs.level.cmp(&rhs.level))
+                .then(lhs.message.cmp(rhs.message))
+        });
+
+        let mut lines = HashSet::new();
+        let mut inline_labels = HashMap::new();
+        let mut multiline_labels = vec![];
+        let mut max_lines = 0;
+
+        for region in regions {
+            let location = region.location.clone();
+
+            if location.end.lines > max_lines {
+                max_lines = location.end.lines;
+            }
+
+            lines.insert(location.start.lines);
+            lines.insert(location.end.lines);
+
+            if location.start.lines == location.end.lines {
+                inline_labels
+                    .entry(location.start.lines)
+                    .or_insert_with(Vec::new)
+                    .push(region);
+            } else {
+                if location.end.lines - location.start.lines < self.multiline_threshold {
+                    lines.extend(location.start.lines..=location.end.lines);
+                }
+
+                multiline_labels.push(region);
+            }
+        }
+
+        // context lines around the labeled ones, as far as the file has lines.
+        if let (Some(first), Some(last)) =
+            (lines.iter().min().copied(), lines.iter().max().copied())
+        {
+            lines.extend(first.saturating_sub(self.context).max(1)..first);
+
+            for line in last + 1..=last + self.context {
+                // the empty "line" after a trailing newline is not context.
+                match files.try_line(id, line) {
+                    None => break,
+                    Some("") if files.try_line(id, line + 1).is_none() => break,
+                    _ => {}
+                }
+
+                lines.insert(line);
+                max_lines = max_lines.max(line);
+            }
+        }
+
+        // primary regions go on top of the caret stack of their line, whatever their column.
+        for stack in inline_labels.values_mut() {
+            stack.sort_by_key(|region| !region.primary);
+        }
+
+        let prefix_width = max_lines.to_string().len().max(self.min_gutter_width);
+
+        let mut lines = lines.drain().collect::<Vec<_>>();
+
+        lines.sort();
+
+        let file_name = files.to_file_name(id)?;
+
+        self.label_color()?;
+
+        writeln!(
+            &mut self.writer,
+            "{} {} {}",
+            " ".repeat(prefix_width),
+            self.charset.corner(),
+            self.path_style.apply(file_name)
+        )?;
+
+        let ident_size = multiline_labels.len() + 1;
+
+        let charset = self.charset;
+
+        let mut prev_line: Option<usize> = None;
+
+        for line in lines {
+            if let Some(prev_line) = prev_line
+                && line > prev_line + 1
+            {
+                let margin = channels(&multiline_labels, ident_size, charset, |_, location| {
+                    location.start.lines <= prev_line && line <= location.end.lines
+                });
+
+                // skipped lines are marked by an elision row, which keeps the channels running.
+                self.label_color()?;
+                writeln!(
+                    &mut self.writer,
+                    "{} {}{}",
+                    " ".repeat(prefix_width),
+                    charset.gap(),
+                    margin.trim_end()
+                )?;
+            }
+
+            prev_line = Some(line);
+
+            let line_content = files.try_line(id, line).unwrap_or_else(|| {
+                log::warn!(
+                    "line {} out of range in `{}`, render it empty.",
+                    line,
+                    file_name
+                );
+                ""
+            });
+
+            self.label_color()?;
+            write!(&mut self.writer, "{:>width$}", line, width = prefix_width)?;
+
+            // mark lines touched by a suggestion, in place of the gutter padding.
+            if modified_lines.contains(&line) {
+                self.suggestion_color()?;
+                write!(&mut self.writer, "~")?;
+                self.label_color()?;
+            } else {
+                write!(&mut self.writer, " ")?;
+            }
+
+            write!(
+                &mut self.writer,
+                "{}{}",
+                charset.vertical(),
+                channels(&multiline_labels, ident_size, charset, |_, location| {
+                    location.start.lines < line && line <= location.end.lines
+                })
+            )?;
+            self.code_color()?;
+
+            let expanded = self.tab_stops.expand(line_content);
+
+            if expanded.chars().any(is_rtl) {
+                if self.bidi_isolation {
+                    writeln!(&mut self.writer, "\u{2066}{}\u{2069}", expanded)?;
+                } else {
+                    log::warn!(
+                        "line {} of `{}` contains right-to-left text, caret alignment may be approximate.",
+                        line,
+                        file_name
+                    );
+                    writeln!(&mut self.writer, "{}", expanded)?;
+                }
+            } else {
+                writeln!(&mut self.writer, "{}", expanded)?;
+            }
+
+            for (index, region) in multiline_labels.iter().enumerate() {
+                let location = &region.location;
+
+                if location.start.lines != line {
+                    continue;
+                }
+
+                let margin = channels(&multiline_labels, ident_size, charset, |other, location| {
+                    (location.start.lines < line && line <= location.end.lines)
+                        || (location.start.lines == line && other < index)
+                });
+
+                let column = ident_size * 2
+                    + self
+                        .tab_stops
+                        .width(before(line_content, location.start.cols));
+
+                // as in rustc, `___^` leads from the channel to a marker under the first character.
+                self.label_color()?;
+                write!(
+                    &mut self.writer,
+                    "{} {}{}{}",
+                    " ".repeat(prefix_width),
+                    charset.vertical(),
+                    margin.chars().take(index * 2 + 1).collect::<String>(),
+                    "_".repeat(column - index * 2 - 1)
+                )?;
+                self.write_boundary(region)?;
+                writeln!(&mut self.writer)?;
+            }
+
+            for region in inline_labels.get(&line).into_iter().flatten() {
+                let location = &region.location;
+
+                let underline = self.theme.underline(region.level);
+
+                let margin = channels(&multiline_labels, ident_size, charset, |_, location| {
+                    location.start.lines <= line && line <= location.end.lines
+                });
+
+                self.label_color()?;
+                write!(
+                    &mut self.writer,
+                    "{} {}{}",
+                    " ".repeat(prefix_width),
+                    charset.vertical(),
+                    margin
+                )?;
+
+                let prefix = self
+                    .tab_stops
+                    .width(before(line_content, location.start.cols));
+
+                // empty regions, e.g. of an empty line, get a single caret at their column.
+                let content = region_width(line_content, location, &self.tab_stops).max(1);
+
+                write!(&mut self.writer, "{}", " ".repeat(prefix))?;
+
+                if region.primary {
+                    self.underline_color(region.level)?;
+                    write!(
+                        &mut self.writer,
+                        "{}",
+                        region
+                            .glyph
+                            .unwrap_or(underline.primary)
+                            .to_string()
+                            .repeat(content)
+                    )?;
+                } else {
+                    write!(
+                        &mut self.writer,
+                        "{}",
+                        region
+                            .glyph
+                            .unwrap_or(underline.secondary)
+                            .to_string()
+                            .repeat(content)
+                    )?;
+                }
+
+                // continuation lines of the message start under the underline.
+                let indent = prefix_width + 2 + ident_size * 2 + prefix;
+
+                let message = self.wrap(region.message, indent + content + 1, indent);
+
+                writeln!(&mut self.writer, " {}", message[0])?;
+
+                for part in &message[1..] {
+                    self.label_color()?;
+                    write!(
+                        &mut self.writer,
+                        "{} {}{}{}",
+                        " ".repeat(prefix_width),
+                        charset.vertical(),
+                        margin,
+                        " ".repeat(prefix)
+                    )?;
+
+                    if region.primary {
+                        self.underline_color(region.level)?;
+                    }
+
+                    writeln!(&mut self.writer, "{}", part)?;
+                }
+            }
+
+            for (index, region) in multiline_labels.iter().enumerate() {
+                let location = &region.location;
+
+                if location.end.lines != line {
+                    continue;
+                }
+
+                let margin = channels(&multiline_labels, ident_size, charset, |other, location| {
+                    location.start.lines <= line
+                        && (line < location.end.lines
+                            || (location.end.lines == line && other > index))
+                });
+
+                let column = (ident_size * 2
+                    + self
+                        .tab_stops
+                        .width(before(line_content, location.end.cols)))
+                .saturating_sub(1);
+
+                // as in rustc, `|___^` closes the channel with a marker under the last character.
+                self.label_color()?;
+                write!(
+                    &mut self.writer,
+                    "{} {}{}{}{}",
+                    " ".repeat(prefix_width),
+                    charset.vertical(),
+                    margin.chars().take(index * 2 + 1).collect::<String>(),
+                    charset.vertical(),
+                    "_".repeat(column.saturating_sub(index * 2 + 2)),
+                )?;
+                self.write_boundary(region)?;
+
+                // continuation lines of the message start under the boundary marker.
+                let indent = column.max(index * 2 + 2);
+
+                let message = self.wrap(
+                    region.message,
+                    prefix_width + 2 + indent + 2,
+                    prefix_width + 2 + indent,
+                );
+
+                writeln!(&mut self.writer, " {}", message[0])?;
+
+                for part in &message[1..] {
+                    self.label_color()?;
+                    write!(
+                        &mut self.writer,
+                        "{} {}{:<indent$}",
+                        " ".repeat(prefix_width),
+                        charset.vertical(),
+                        margin,
+                    )?;
+                    writeln!(&mut self.writer, "{}", part)?;
+                }
+            }
+        }
+
+        Ok(prefix_width)
+    }
+}
+
+/// Resolve the `Location` range of a region, swapping reversed bounds instead of panicking later.
+fn to_location<F>(
+    files: &F,
+    id: FileId,
+    range: &Range<usize>,
+) -> std::result::Result<Range<Location>, FilesError>
+where
+    F: Files,
+{
+    let location = files.to_location(id, range)?;
+
+    if (location.start.lines, location.start.cols) > (location.end.lines, location.end.cols) {
+        log::warn!(
+            "reversed label range {}..{} in `{}`, swap the bounds.",
+            range.start,
+            range.end,
+            files.to_file_name(id)?
+        );
+
+        return Ok(location.end..location.start);
+    }
+
+    Ok(location)
+}
+
+/// Returns `range`, with reversed bounds swapped as [`to_location`] does for regions.
+fn to_range<F>(
+    files: &F,
+    id: FileId,
+    range: &Range<usize>,
+) -> std::result::Result<Range<usize>, FilesError>
+where
+    F: Files,
+{
+    if range.start > range.end {
+        log::warn!(
+            "reversed suggestion range {}..{} in `{}`, swap the bounds.",
+            range.start,
+            range.end,
+            files.to_file_name(id)?
+        );
+
+        return Ok(range.end..range.start);
+    }
+
+    Ok(range.clone())
+}
+
+/// Returns the display width of the underline drawn for `label_region` of `file`.
+///
+/// Regions spanning several lines are measured up to the end of their first line.
+pub fn underline_width<F>(
+    files: &F,
+    label_region: &LabelRegion<'_>,
+    file: FileId,
+) -> std::result::Result<usize, FilesError>
+where
+    F: Files,
+{
+    let location = to_location(files, file, &label_region.byte_range(files, file)?)?;
+
+    Ok(region_width(
+        files.as_str(file, location.start.lines)?,
+        &location,
+        &TabStops::default(),
+    ))
+}
+
+/// Returns the display width of `location` on its first line `line_content`.
+fn region_width(line_content: &str, location: &Range<Location>, tab_stops: &TabStops) -> usize {
+    let start = before(line_content, location.start.cols);
+
+    let end = if location.start.lines == location.end.lines {
+        before(line_content, location.end.cols)
+    } else {
+        line_content
+    };
+
+    tab_stops.width(end).saturating_sub(tab_stops.width(start))
+}
+
+/// Returns the content of `line_content` before the 1-based byte column `cols`.
+///
+/// Columns past the end, e.g. of a line break, are clamped to the end of the line, and
+/// columns inside of a char to its start, so that slicing never panics.
+fn before(line_content: &str, cols: usize) -> &str {
+    let mut end = cols.saturating_sub(1).min(line_content.len());
+
+    while !line_content.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    &line_content[..end]
+}
+
+/// Resolve the primary and secondary regions of `label`, the latter not in quiet mode.
+fn label_regions<'r, F>(
+    files: &F,
+    level: Level,
+    label: &'r Label<'_>,
+    verbosity: Verbosity,
+) -> std::result::Result<Vec<Region<'r>>, FilesError>
+where
+    F: Files,
+{
+    let mut regions = vec![Region {
+        location: to_location(files, label.id, &label.primary.byte_range(files, label.id)?)?,
+        message: &label.primary.message,
+        primary: label.style == LabelStyle::Primary,
+        level: label.level.unwrap_or(level),
+        glyph: label.primary.glyph,
+    }];
+
+    if verbosity == Verbosity::Quiet {
+        return Ok(regions);
+    }
+
+    for region in &label.secondary {
+        regions.push(Region {
+            location: to_location(files, label.id, &region.byte_range(files, label.id)?)?,
+            message: &region.message,
+            primary: false,
+            level: label.level.unwrap_or(level),
+            glyph: region.glyph,
+        });
+    }
+
+    Ok(regions)
+}
+
+/// Returns the lines touched by the suggestions of `label`.
+fn suggested_lines<F>(
+    files: &F,
+    label: &Label<'_>,
+) -> std::result::Result<HashSet<usize>, FilesError>
+where
+    F: Files,
+{
+    let mut lines = HashSet::new();
+
+    for suggestion in &label.suggestions {
+        let location = to_location(files, label.id, &suggestion.range)?;
+
+        lines.extend(location.start.lines..=location.end.lines);
+    }
+
+    Ok(lines)
+}
+
+/// Returns the file that all labels of `diagnostic` are in, if there is exactly one.
+fn single_file(diagnostic: &Diagnostic) -> Option<FileId> {
+    let id = diagnostic.labels.first()?.id;
+
+    diagnostic
+        .labels
+        .iter()
+        .all(|label| label.id == id)
+        .then_some(id)
+}
+
+/// Split `text` on whitespace into lines of at most `first` columns for the first line
+/// and `rest` columns for the others.
+///
+/// Text that fits is returned as is, words wider than a line are not broken.
+fn wrap_text(text: &str, first: usize, rest: usize) -> Vec<&str> {
+    if text.width() <= first {
+        return vec![text];
+    }
+
+    let mut words = vec![];
+    let mut word_start = None;
+
+    for (offset, c) in text.char_indices() {
+        if c.is_whitespace() {
+            if let Some(start) = word_start.take() {
+                words.push(start..offset);
+            }
+        } else if word_start.is_none() {
+            word_start = Some(offset);
+        }
+    }
+
+    if let Some(start) = word_start {
+        words.push(start..text.len());
+    }
+
+    let mut lines = vec![];
+    let mut limit = first;
+    let mut line: Option<Range<usize>> = None;
+
+    for word in words {
+        line = match line {
+            Some(line) if text[line.start..word.end].width() > limit => {
+                lines.push(&text[line]);
+                limit = rest;
+                Some(word)
+            }
+            Some(line) => Some(line.start..word.end),
+            None => Some(word),
+        };
+    }
+
+    lines.extend(line.map(|line| &text[line]));
+
+    if lines.is_empty() {
+        lines.push(text);
+    }
+
+    lines
+}
+
+/// Returns true if `c` is in a block of a right-to-left script, e.g. Hebrew or Arabic.
+fn is_rtl(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0590}'..='\u{08FF}'
+            | '\u{FB1D}'..='\u{FDFF}'
+            | '\u{FE70}'..='\u{FEFF}'
+            | '\u{10800}'..='\u{10FFF}'
+            | '\u{1E800}'..='\u{1EFFF}'
+    )
+}
+
+/// Returns the left margin of one snippet row, which draws a vertical bar in the channel of
+/// every multiline region that `active` accepts.
+fn channels<F>(
+    multiline_labels: &[Region<'_>],
+    ident_size: usize,
+    charset: CharSet,
+    active: F,
+) -> String
+where
+    F: Fn(usize, &Range<Location>) -> bool,
+{
+    let mut margin = vec![' '; ident_size * 2];
+
+    for (index, region) in multiline_labels.iter().enumerate() {
+        if active(index, &region.location) {
+            margin[index * 2 + 1] = charset.vertical();
+        }
+    }
+
+    margin.into_iter().collect()
+}
+impl<S> Renderer for Layout<S>
+where
+    S: Sink,
+{
+    type Error = std::io::Error;
+
+    fn render<'a, F, D>(&mut self, files: &F, diagnostic: D) -> Result<()>
+    where
+        F: Files,
+        crate::Diagnostic<'a>: From<D>,
+    {
+        let diagnostic: Diagnostic<'a> = diagnostic.into();
+
+        self.write_diagnostic(files, &diagnostic)
+    }
+
+    fn render_ref<F>(&mut self, files: &F, diagnostic: &Diagnostic<'_>) -> Result<()>
+    where
+        F: Files,
+    {
+        self.write_diagnostic(files, diagnostic)
+    }
+
+    fn render_all<'a, F, I, D>(&mut self, files: &F, diagnostics: I) -> Result<()>
+    where
+        F: Files,
+        I: IntoIterator<Item = D>,
+        Diagnostic<'a>: From<D>,
+    {
+        if !self.coalesce {
+            for diagnostic in diagnostics {
+                self.render(files, diagnostic)?;
+            }
+
+            return Ok(());
+        }
+
+        let diagnostics = diagnostics
+            .into_iter()
+            .map(Diagnostic::from)
+            .collect::<Vec<_>>();
+
+        let mut rendered = vec![false; diagnostics.len()];
+
+        for (index, diagnostic) in diagnostics.iter().enumerate() {
+            if rendered[index] {
+                continue;
+            }
+
+            let Some(id) = single_file(diagnostic)
+                .filter(|_| !self.strict || diagnostic.validate(files).is_ok())
+            else {
+                self.write_diagnostic(files, diagnostic)?;
+                continue;
+            };
+
+            let mut group = vec![];
+
+            for (other, diagnostic) in diagnostics.iter().enumerate().skip(index) {
+                if !rendered[other] && single_file(diagnostic) == Some(id) {
+                    rendered[other] = true;
+                    group.push(diagnostic);
+                }
+            }
+
+            self.write_coalesced(files, id, &group)?;
+        }
+
+        Ok(())
+    }
+
+    fn render_section<'a, F, I, D>(&mut self, title: &str, files: &F, diagnostics: I) -> Result<()>
+    where
+        F: Files,
+        I: IntoIterator<Item = D>,
+        Diagnostic<'a>: From<D>,
+    {
+        self.label_color()?;
+        write!(&mut self.writer, "=== ")?;
+        self.text_color()?;
+        write!(&mut self.writer, "{}", title)?;
+        self.label_color()?;
+        writeln!(&mut self.writer, " ===")?;
+        self.writer.flush()?;
+
+        self.render_all(files, diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{relative_to, wrap_text};
+
+    #[test]
+    fn test_relative_to() {
+        let name = "/home/u/proj/src/main.rs";
+
+        assert_eq!(relative_to(name, Path::new("/home/u/proj")), "src/main.rs");
+        assert_eq!(relative_to(name, Path::new("/tmp")), name);
+    }
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(wrap_text("fits  as is", 11, 4), ["fits  as is"]);
+        assert_eq!(
+            wrap_text("expected type `String`", 14, 8),
+            ["expected type", "`String`"]
+        );
+        assert_eq!(
+            wrap_text("a  b c unbreakable d", 4, 6),
+            ["a  b", "c", "unbreakable", "d"]
+        );
+    }
+}
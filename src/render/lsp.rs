@@ -0,0 +1,193 @@
+//! Conversion of diagnostics into [Language Server Protocol] diagnostics.
+//!
+//! [Language Server Protocol]: https://microsoft.github.io/language-server-protocol/
+
+use std::ops::Range;
+
+use lsp_types::{
+    CodeDescription, DiagnosticRelatedInformation, DiagnosticSeverity, NumberOrString, Position,
+    Url,
+};
+
+use crate::{Code, Diagnostic, FileId, LabelStyle, Level};
+
+use super::{Files, FilesError};
+
+impl Diagnostic<'_> {
+    /// Convert this diagnostic into one LSP diagnostic per primary-styled label, paired with
+    /// the url of the label's file.
+    ///
+    /// Ranges are zero-based, with characters counted in UTF-16 code units. The regions of
+    /// the other labels and the secondary regions become `relatedInformation`, notes and help
+    /// lines are appended to the message. File names are resolved against the current directory, labels
+    /// whose file name is no valid file path are skipped.
+    pub fn to_lsp<F>(&self, files: &F) -> Result<Vec<(Url, lsp_types::Diagnostic)>, FilesError>
+    where
+        F: Files,
+    {
+        let mut message = self.message.to_string();
+
+        for note in self.nodes.iter().chain(&self.helps) {
+            message.push('\n');
+            message.push_str(note);
+        }
+
+        let mut related_information = vec![];
+
+        for label in &self.labels {
+            let regions = label
+                .secondary
+                .iter()
+                .chain((label.style == LabelStyle::Secondary).then_some(&label.primary));
+
+            for region in regions {
+                let Some(uri) = to_url(files, label.id)? else {
+                    continue;
+                };
+
+                related_information.push(DiagnosticRelatedInformation {
+                    location: lsp_types::Location {
+                        uri,
+                        range: to_range(files, label.id, &region.byte_range(files, label.id)?)?,
+                    },
+                    message: region.message.to_string(),
+                });
+            }
+        }
+
+        let mut diagnostics = vec![];
+
+        for label in &self.labels {
+            if label.style != LabelStyle::Primary {
+                continue;
+            }
+
+            let Some(uri) = to_url(files, label.id)? else {
+                continue;
+            };
+
+            let diagnostic = lsp_types::Diagnostic {
+                range: to_range(files, label.id, &label.primary.byte_range(files, label.id)?)?,
+                severity: Some(match self.level {
+                    Level::Bug | Level::Error => DiagnosticSeverity::ERROR,
+                    Level::Warning => DiagnosticSeverity::WARNING,
+                    Level::Note => DiagnosticSeverity::INFORMATION,
+                    Level::Help => DiagnosticSeverity::HINT,
+                }),
+                code: self.code.as_ref().map(|code| match code {
+                    Code::Number(number) => i32::try_from(*number)
+                        .map(NumberOrString::Number)
+                        .unwrap_or_else(|_| NumberOrString::String(code.to_string())),
+                    Code::Name(name) => NumberOrString::String(name.to_string()),
+                }),
+                code_description: self
+                    .url
+                    .as_deref()
+                    .and_then(|url| Url::parse(url).ok())
+                    .map(|href| CodeDescription { href }),
+                message: message.clone(),
+                related_information: (!related_information.is_empty())
+                    .then(|| related_information.clone()),
+                ..Default::default()
+            };
+
+            diagnostics.push((uri, diagnostic));
+        }
+
+        Ok(diagnostics)
+    }
+}
+
+fn to_url<F>(files: &F, id: FileId) -> Result<Option<Url>, FilesError>
+where
+    F: Files,
+{
+    let name = files.to_file_name(id)?;
+
+    Ok(std::path::absolute(name)
+        .ok()
+        .and_then(|path| Url::from_file_path(path).ok()))
+}
+
+fn to_range<F>(files: &F, id: FileId, range: &Range<usize>) -> Result<lsp_types::Range, FilesError>
+where
+    F: Files,
+{
+    let location = files.to_location(id, range)?;
+
+    let position = |location: crate::Location| {
+        let line = files.try_line(id, location.lines).unwrap_or("");
+
+        let prefix = line.get(..location.cols - 1).unwrap_or(line);
+
+        Position {
+            line: (location.lines - 1) as u32,
+            character: prefix.encode_utf16().count() as u32,
+        }
+    };
+
+    Ok(lsp_types::Range {
+        start: position(location.start),
+        end: position(location.end),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{DiagnosticSeverity, NumberOrString, Position, Url};
+
+    use crate::{Diagnostic, Label, SourceCodes};
+
+    #[test]
+    fn test_to_lsp() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("/src/main.rs", "fn main() {\n    let s = \"é\"; x\n}\n");
+        let other = files.add("/src/lib.rs", "pub fn x() {}\n");
+
+        let diagnostic = Diagnostic::error("unresolved name")
+            .with_code("E0425")
+            .with_code_url("https://doc.rust-lang.org/error_codes/E0425.html")
+            .with_label(Label::new(id, 30..31, "not found").with_secondary(24..28, "a string"))
+            .with_label(Label::secondary(other, 7..8, "similar name"))
+            .with_note("names must be in scope");
+
+        let lsp = diagnostic.to_lsp(&files).unwrap();
+
+        assert_eq!(lsp.len(), 1);
+
+        let (uri, lsp) = &lsp[0];
+
+        assert_eq!(uri, &Url::parse("file:///src/main.rs").unwrap());
+        // `é` is 2 bytes but 1 UTF-16 code unit.
+        assert_eq!(lsp.range.start, Position::new(1, 17));
+        assert_eq!(lsp.range.end, Position::new(1, 18));
+        assert_eq!(lsp.severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(lsp.code, Some(NumberOrString::String("E0425".into())));
+        assert_eq!(
+            lsp.code_description.as_ref().unwrap().href.as_str(),
+            "https://doc.rust-lang.org/error_codes/E0425.html"
+        );
+        assert_eq!(lsp.message, "unresolved name\nnames must be in scope");
+
+        let related = lsp.related_information.as_ref().unwrap();
+
+        assert_eq!(related.len(), 2);
+        assert_eq!(related[0].message, "a string");
+        assert_eq!(related[0].location.range.start, Position::new(1, 12));
+        assert_eq!(
+            related[1].location.uri,
+            Url::parse("file:///src/lib.rs").unwrap()
+        );
+        assert_eq!(related[1].location.range.start, Position::new(0, 7));
+
+        let numeric = Diagnostic::warning("unused")
+            .with_code(308)
+            .with_label(Label::new(other, 7..8, "never used"))
+            .to_lsp(&files)
+            .unwrap();
+
+        assert_eq!(numeric[0].1.code, Some(NumberOrString::Number(308)));
+        assert_eq!(numeric[0].1.severity, Some(DiagnosticSeverity::WARNING));
+    }
+}
@@ -1,7 +1,40 @@
+#[allow(clippy::module_inception)]
 mod render;
 pub use render::*;
 
 mod files;
 pub use files::*;
 
+mod theme;
+pub use theme::*;
+
+mod auto;
+pub use auto::*;
+
+mod router;
+pub use router::*;
+
+mod explain;
+pub use explain::*;
+
+mod layout;
+
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub mod cargo;
+pub mod github;
+pub mod html;
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub mod json;
+#[cfg(feature = "lsp")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lsp")))]
+pub mod lsp;
+pub mod plain;
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub mod sarif;
 pub mod term;
+#[cfg(feature = "ratatui")]
+#[cfg_attr(docsrs, doc(cfg(feature = "ratatui")))]
+pub mod tui;
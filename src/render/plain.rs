@@ -0,0 +1,300 @@
+//! A renderer implementation that writes the terminal layout without any escape sequences.
+
+use std::io::{Result, Stdout, Write};
+
+use crate::{Code, Diagnostic, Label};
+
+use super::{
+    CodeExplanations, Files, Renderer,
+    layout::{Layout, Uncolored},
+    term::{CharSet, PathStyle, Verbosity},
+};
+
+/// A diagnostic reporting renderer that writes exactly the layout of [`Term`](super::term::Term)
+/// (gutter, underlines, notes) into any [`Write`], but never any color or hyperlink escape
+/// sequence, e.g. for log files and snapshot tests.
+pub struct Plain<W = Stdout> {
+    layout: Layout<Uncolored<W>>,
+}
+
+impl Default for Plain {
+    fn default() -> Self {
+        Self::with_writer(std::io::stdout())
+    }
+}
+
+impl<W> Plain<W>
+where
+    W: Write,
+{
+    /// Create a new `Plain` that renders into `writer`.
+    pub fn with_writer(writer: W) -> Self {
+        Self {
+            layout: Layout::with_writer(Uncolored::new(writer)),
+        }
+    }
+
+    /// Consume this `Plain`, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.layout.into_inner().into_inner()
+    }
+
+    /// Set the minimum width of the line number gutter, so that blocks of small files
+    /// align with the blocks of larger ones.
+    pub fn with_min_gutter_width(mut self, width: usize) -> Self {
+        self.layout.min_gutter_width = width;
+        self
+    }
+
+    /// Set whether [`render_all`](Renderer::render_all) merges diagnostics whose labels are all
+    /// in the same file into a single file block, instead of repeating the file header and the
+    /// source lines for each one.
+    pub fn with_coalesce(mut self, coalesce: bool) -> Self {
+        self.layout.coalesce = coalesce;
+        self
+    }
+
+    /// Set the maximum number of lines a multiline region may span before it is rendered
+    /// in compact form: only its first and last lines are shown, joined by an elision row.
+    pub fn with_multiline_threshold(mut self, lines: usize) -> Self {
+        self.layout.multiline_threshold = lines;
+        self
+    }
+
+    /// Set whether identical notes of a diagnostic are rendered only once, which is the default.
+    pub fn with_dedup_notes(mut self, dedup: bool) -> Self {
+        self.layout.dedup_notes = dedup;
+        self
+    }
+
+    /// Set whether source lines containing right-to-left text are wrapped in bidi isolate
+    /// controls, so that terminals keep their characters in logical order under the carets.
+    pub fn with_bidi_isolation(mut self, isolate: bool) -> Self {
+        self.layout.bidi_isolation = isolate;
+        self
+    }
+
+    /// Set whether diagnostics that fail [`validate`](Diagnostic::validate) are replaced by an
+    /// internal error [`Bug`](crate::Level::Bug) diagnostic, instead of failing or being patched up.
+    ///
+    /// This is meant for development, to catch span bugs of a compiler.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.layout.strict = strict;
+        self
+    }
+
+    /// Set how file names are shown in the header of file blocks, [`PathStyle::Full`] by default.
+    pub fn with_path_style(mut self, style: PathStyle) -> Self {
+        self.layout.path_style = style;
+        self
+    }
+
+    /// Set whether a ` --> file:line:col` line pointing at the primary label follows the header
+    /// of each diagnostic, which is the default.
+    pub fn with_locator(mut self, locator: bool) -> Self {
+        self.layout.locator = locator;
+        self
+    }
+
+    /// Set how much detail is rendered for each diagnostic, [`Verbosity::Normal`] by default.
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.layout.verbosity = verbosity;
+        self
+    }
+
+    /// Set the width in columns that label messages and notes are wrapped to.
+    ///
+    /// Without a width, which is the default, messages are not wrapped.
+    pub fn with_width(mut self, cols: usize) -> Self {
+        self.layout.width = Some(cols);
+        self
+    }
+
+    /// Set the number of unlabeled source lines shown before the first and after the last
+    /// labeled line of a file block, 0 by default.
+    pub fn with_context(mut self, lines: usize) -> Self {
+        self.layout.context = lines;
+        self
+    }
+
+    /// Set the characters of the gutter and the multiline connectors, [`CharSet::Unicode`] by default.
+    pub fn with_charset(mut self, charset: CharSet) -> Self {
+        self.layout.charset = charset;
+        self
+    }
+
+    /// Set the registry of code explanations, so that diagnostics with a registered code point
+    /// to the command that explains it, and [`render_explanation`](Self::render_explanation)
+    /// can render it.
+    pub fn with_explanations(mut self, explanations: CodeExplanations) -> Self {
+        self.layout.explanations = Some(explanations);
+        self
+    }
+
+    /// Set whether the `[code]` of diagnostics is shown in the header, which is the default.
+    ///
+    /// This only affects the layout, structured renderers keep the code.
+    pub fn with_code_display(mut self, display: bool) -> Self {
+        self.layout.code_display = display;
+        self
+    }
+
+    /// Set whether the [`Stage`](crate::Stage) of diagnostics is shown in the header,
+    /// e.g. `error[000308] (parsing SVG): ...`, off by default.
+    pub fn with_stage_display(mut self, display: bool) -> Self {
+        self.layout.stage_display = display;
+        self
+    }
+
+    /// Set the display columns that tabs in the source expand to, e.g. `[8, 16, 24]`.
+    ///
+    /// Past the last stop, and by default, tabs expand to uniform stops every
+    /// [tab width](Self::with_tab_width) columns.
+    pub fn with_tab_stops<I>(mut self, stops: I) -> Self
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        self.layout.set_tab_stops(stops);
+        self
+    }
+
+    /// Set the distance between uniform tab stops, 4 columns by default.
+    ///
+    /// A width of 0 is treated as 1.
+    pub fn with_tab_width(mut self, width: usize) -> Self {
+        self.layout.set_tab_width(width);
+        self
+    }
+
+    /// Render the long-form explanation of `code` from the registry set by
+    /// [`with_explanations`](Self::with_explanations).
+    ///
+    /// Markdown headings are rendered in the header style and fenced code blocks are indented.
+    /// Returns false, rendering nothing, if `code` has no registered explanation.
+    pub fn render_explanation<'c, C>(&mut self, code: C) -> Result<bool>
+    where
+        Code<'c>: From<C>,
+    {
+        self.layout.render_explanation(code)
+    }
+
+    /// Render a single label standalone: its file block and underlines, without the diagnostic
+    /// header and notes. The primary region is styled as an [`Error`](crate::Level::Error).
+    pub fn render_label<F>(&mut self, files: &F, label: &Label<'_>) -> Result<()>
+    where
+        F: Files,
+    {
+        self.layout.render_label(files, label)
+    }
+}
+
+impl<W> Renderer for Plain<W>
+where
+    W: Write,
+{
+    type Error = std::io::Error;
+
+    fn render<'a, F, D>(&mut self, files: &F, diagnostic: D) -> Result<()>
+    where
+        F: Files,
+        Diagnostic<'a>: From<D>,
+    {
+        self.layout.render(files, diagnostic)
+    }
+
+    fn render_ref<F>(&mut self, files: &F, diagnostic: &Diagnostic<'_>) -> Result<()>
+    where
+        F: Files,
+    {
+        self.layout.render_ref(files, diagnostic)
+    }
+
+    fn render_all<'a, F, I, D>(&mut self, files: &F, diagnostics: I) -> Result<()>
+    where
+        F: Files,
+        I: IntoIterator<Item = D>,
+        Diagnostic<'a>: From<D>,
+    {
+        self.layout.render_all(files, diagnostics)
+    }
+
+    fn render_section<'a, F, I, D>(&mut self, title: &str, files: &F, diagnostics: I) -> Result<()>
+    where
+        F: Files,
+        I: IntoIterator<Item = D>,
+        Diagnostic<'a>: From<D>,
+    {
+        self.layout.render_section(title, files, diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use termcolor::Ansi;
+
+    use crate::{Diagnostic, Label, Renderer, SourceCodes, term::Term};
+
+    use super::Plain;
+
+    #[test]
+    fn test_plain() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("src/main.rs", "fn main() {\n    let x = 1\n}\n");
+
+        let diagnostic = || {
+            Diagnostic::error("expected `;`")
+                .with_code("E0308")
+                .with_code_url("https://example.com/E0308")
+                .with_label(Label::new(id, 16..25, "missing `;`").with_secondary(26..27, "here"))
+                .with_note("statements end with `;`")
+        };
+
+        let mut plain = Plain::with_writer(vec![]).with_context(1);
+
+        plain.render(&files, diagnostic()).unwrap();
+
+        let output = String::from_utf8(plain.into_inner()).unwrap();
+
+        assert!(!output.contains('\x1b'));
+        assert!(output.starts_with("error[E0308]: expected `;`\n"));
+        assert!(output.contains("1 │  fn main() {\n"));
+
+        let mut term = Term::with_writer(Ansi::new(vec![])).with_context(1);
+
+        term.render(&files, diagnostic()).unwrap();
+
+        let colored = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        // the same layout as `Term`, without escape sequences.
+        assert_eq!(strip_escapes(&colored), output);
+    }
+
+    fn strip_escapes(text: &str) -> String {
+        let mut output = String::new();
+        let mut chars = text.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '\x1b' {
+                output.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                // CSI, e.g. `\x1b[31m`, up to its final letter.
+                Some('[') => chars
+                    .by_ref()
+                    .take_while(|c| !c.is_ascii_alphabetic())
+                    .for_each(drop),
+                // OSC, up to the `\x1b\\` terminator.
+                Some(']') => {
+                    chars.by_ref().take_while(|c| *c != '\x1b').for_each(drop);
+                    chars.next();
+                }
+                _ => {}
+            }
+        }
+
+        output
+    }
+}
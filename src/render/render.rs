@@ -1,4 +1,6 @@
-use crate::Diagnostic;
+use std::io;
+
+use crate::{Diagnostic, FileId, Level};
 
 use super::Files;
 
@@ -12,4 +14,200 @@ pub trait Renderer {
     where
         F: Files,
         Diagnostic<'a>: From<D>;
+
+    /// Render a borrowed diagnostic.
+    ///
+    /// The default implementation renders a clone of `diagnostic`, renderers that can render
+    /// from a reference should override it.
+    fn render_ref<F>(&mut self, files: &F, diagnostic: &Diagnostic<'_>) -> Result<(), Self::Error>
+    where
+        F: Files,
+    {
+        self.render(files, diagnostic.clone())
+    }
+
+    /// Render a batch of diagnostics in order.
+    fn render_all<'a, F, I, D>(&mut self, files: &F, diagnostics: I) -> Result<(), Self::Error>
+    where
+        F: Files,
+        I: IntoIterator<Item = D>,
+        Diagnostic<'a>: From<D>,
+    {
+        for diagnostic in diagnostics {
+            self.render(files, diagnostic)?;
+        }
+
+        Ok(())
+    }
+
+    /// Render a batch of diagnostics in order, beneath a `title` banner, e.g. for report files.
+    ///
+    /// The default implementation renders no banner, renderers that can should override it.
+    fn render_section<'a, F, I, D>(
+        &mut self,
+        title: &str,
+        files: &F,
+        diagnostics: I,
+    ) -> Result<(), Self::Error>
+    where
+        F: Files,
+        I: IntoIterator<Item = D>,
+        Diagnostic<'a>: From<D>,
+    {
+        let _ = title;
+
+        self.render_all(files, diagnostics)
+    }
+
+    /// Render a batch of diagnostics in order, stopping after the first [`Error`](Level::Error)
+    /// or [`Bug`](Level::Bug) diagnostic.
+    ///
+    /// Returns true if diagnostics after the first error were suppressed.
+    fn render_until_first_error<'a, F, I, D>(
+        &mut self,
+        files: &F,
+        diagnostics: I,
+    ) -> Result<bool, Self::Error>
+    where
+        F: Files,
+        I: IntoIterator<Item = D>,
+        Diagnostic<'a>: From<D>,
+    {
+        let mut diagnostics = diagnostics.into_iter().map(Diagnostic::from);
+
+        for diagnostic in diagnostics.by_ref() {
+            let level = diagnostic.level;
+
+            self.render::<F, Diagnostic<'a>>(files, diagnostic)?;
+
+            if matches!(level, Level::Error | Level::Bug) {
+                return Ok(diagnostics.next().is_some());
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+/// Number of rendered diagnostics per [`Level`].
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct LevelCounts {
+    /// Number of [`Bug`](Level::Bug) diagnostics.
+    pub bugs: usize,
+    /// Number of [`Error`](Level::Error) diagnostics.
+    pub errors: usize,
+    /// Number of [`Warning`](Level::Warning) diagnostics.
+    pub warnings: usize,
+    /// Number of [`Note`](Level::Note) diagnostics.
+    pub notes: usize,
+    /// Number of [`Help`](Level::Help) diagnostics.
+    pub helps: usize,
+}
+
+impl LevelCounts {
+    /// Count one more diagnostic with severity `level`.
+    pub fn add(&mut self, level: Level) {
+        *self.count_mut(level) += 1;
+    }
+
+    /// Returns the number of diagnostics with severity `level`.
+    pub fn count(&self, level: Level) -> usize {
+        match level {
+            Level::Bug => self.bugs,
+            Level::Error => self.errors,
+            Level::Warning => self.warnings,
+            Level::Note => self.notes,
+            Level::Help => self.helps,
+        }
+    }
+
+    /// Returns the number of diagnostics of all levels.
+    pub fn total(&self) -> usize {
+        self.bugs + self.errors + self.warnings + self.notes + self.helps
+    }
+
+    fn count_mut(&mut self, level: Level) -> &mut usize {
+        match level {
+            Level::Bug => &mut self.bugs,
+            Level::Error => &mut self.errors,
+            Level::Warning => &mut self.warnings,
+            Level::Note => &mut self.notes,
+            Level::Help => &mut self.helps,
+        }
+    }
+}
+
+/// Render diagnostics one by one as `diagnostics` produces them, without collecting them first,
+/// and returns the number of rendered diagnostics per level.
+///
+/// This suits long-running analyses that report diagnostics incrementally.
+pub fn render_stream<'a, R, F, I, D>(
+    renderer: &mut R,
+    files: &F,
+    diagnostics: I,
+) -> Result<LevelCounts, R::Error>
+where
+    R: Renderer,
+    F: Files,
+    I: IntoIterator<Item = D>,
+    Diagnostic<'a>: From<D>,
+{
+    let mut counts = LevelCounts::default();
+
+    for diagnostic in diagnostics {
+        let diagnostic = Diagnostic::from(diagnostic);
+
+        counts.add(diagnostic.level);
+
+        renderer.render::<F, Diagnostic<'a>>(files, diagnostic)?;
+    }
+
+    Ok(counts)
+}
+
+/// Check that every label of `diagnostic` references a file of `files`.
+pub(crate) fn check_files<F>(files: &F, diagnostic: &Diagnostic<'_>) -> io::Result<()>
+where
+    F: Files,
+{
+    for label in &diagnostic.labels {
+        check_file(files, label.id)?;
+    }
+
+    Ok(())
+}
+
+/// Check that `id` references a file of `files`.
+pub(crate) fn check_file<F>(files: &F, id: FileId) -> io::Result<()>
+where
+    F: Files,
+{
+    if !files.contains(id) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "diagnostic references file id {} but no such source was provided",
+                id.0
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// An object-safe counterpart of [`Renderer`], so that the renderer can be selected at runtime.
+///
+/// This trait is implemented for every [`Renderer`] whose error type is [`io::Error`].
+pub trait DynRenderer {
+    /// Render a borrowed diagnostic.
+    fn render_dyn(&mut self, files: &dyn Files, diagnostic: &Diagnostic<'_>) -> io::Result<()>;
+}
+
+impl<R> DynRenderer for R
+where
+    R: Renderer<Error = io::Error>,
+{
+    fn render_dyn(&mut self, files: &dyn Files, diagnostic: &Diagnostic<'_>) -> io::Result<()> {
+        self.render_ref(&files, diagnostic)
+    }
 }
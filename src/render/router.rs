@@ -0,0 +1,115 @@
+use std::{collections::HashMap, io::Result};
+
+use crate::{Diagnostic, Stage};
+
+use super::{DynRenderer, Files, Renderer};
+
+/// A renderer that dispatches diagnostics to an inner renderer by their [`Stage`],
+/// so that different stages of a pipeline can use different output formats.
+///
+/// Diagnostics without a stage, or with a stage that has no route, go to the fallback renderer.
+pub struct StageRouter {
+    routes: HashMap<Stage, Box<dyn DynRenderer>>,
+    fallback: Box<dyn DynRenderer>,
+}
+
+impl StageRouter {
+    /// Create a new router that renders unrouted diagnostics with `fallback`.
+    pub fn new<R>(fallback: R) -> Self
+    where
+        R: DynRenderer + 'static,
+    {
+        Self {
+            routes: HashMap::new(),
+            fallback: Box::new(fallback),
+        }
+    }
+
+    /// Render diagnostics of `stage` with `renderer`.
+    pub fn with_route<R>(mut self, stage: Stage, renderer: R) -> Self
+    where
+        R: DynRenderer + 'static,
+    {
+        self.routes.insert(stage, Box::new(renderer));
+        self
+    }
+}
+
+impl Renderer for StageRouter {
+    type Error = std::io::Error;
+
+    fn render<'a, F, D>(&mut self, files: &F, diagnostic: D) -> Result<()>
+    where
+        F: Files,
+        Diagnostic<'a>: From<D>,
+    {
+        let diagnostic: Diagnostic<'a> = diagnostic.into();
+
+        let renderer = match diagnostic
+            .stage
+            .and_then(|stage| self.routes.get_mut(&stage))
+        {
+            Some(renderer) => renderer,
+            None => &mut self.fallback,
+        };
+
+        renderer.render_dyn(files, &diagnostic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::SourceCodes;
+
+    /// A renderer that records the messages it renders.
+    struct Capture(Rc<RefCell<Vec<String>>>);
+
+    impl Renderer for Capture {
+        type Error = std::io::Error;
+
+        fn render<'a, F, D>(&mut self, _: &F, diagnostic: D) -> Result<()>
+        where
+            F: Files,
+            Diagnostic<'a>: From<D>,
+        {
+            let diagnostic: Diagnostic<'a> = diagnostic.into();
+
+            self.0.borrow_mut().push(diagnostic.message.to_string());
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_stage_router() {
+        let files = SourceCodes::default();
+
+        let parsing = Rc::new(RefCell::new(vec![]));
+        let codegen = Rc::new(RefCell::new(vec![]));
+        let fallback = Rc::new(RefCell::new(vec![]));
+
+        let mut router = StageRouter::new(Capture(fallback.clone()))
+            .with_route(Stage::Parsing("SVG"), Capture(parsing.clone()))
+            .with_route(Stage::Codegen("wasm"), Capture(codegen.clone()));
+
+        router
+            .render_all(
+                &files,
+                [
+                    Diagnostic::error("unexpected token").with_stage(Stage::Parsing("SVG")),
+                    Diagnostic::error("unsupported opcode").with_stage(Stage::Codegen("wasm")),
+                    Diagnostic::error("unclosed tag").with_stage(Stage::Parsing("SVG")),
+                    Diagnostic::warning("slow path").with_stage(Stage::Analysis("SVG")),
+                    Diagnostic::note("done"),
+                ],
+            )
+            .unwrap();
+
+        assert_eq!(*parsing.borrow(), ["unexpected token", "unclosed tag"]);
+        assert_eq!(*codegen.borrow(), ["unsupported opcode"]);
+        assert_eq!(*fallback.borrow(), ["slow path", "done"]);
+    }
+}
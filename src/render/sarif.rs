@@ -0,0 +1,369 @@
+//! A renderer implementation that collects diagnostics into a [SARIF] 2.1.0 log.
+//!
+//! [SARIF]: https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+
+use std::{borrow::Cow, io::Result, ops::Range};
+
+use serde_json::{Map, Value, json};
+
+use crate::{Diagnostic, FileId, Level, Location};
+
+use super::{Files, FilesError, Renderer, check_files};
+
+/// A diagnostic reporting renderer that accumulates diagnostics as SARIF `results`, for
+/// code-scanning annotations in CI.
+///
+/// SARIF is a single document, so nothing is written per diagnostic, call
+/// [`finish`](Self::finish) once every diagnostic is rendered.
+#[derive(Debug, Clone)]
+pub struct Sarif {
+    tool_name: Cow<'static, str>,
+    rules: Vec<Value>,
+    results: Vec<Value>,
+}
+
+impl Default for Sarif {
+    fn default() -> Self {
+        Self {
+            tool_name: "ceport".into(),
+            rules: vec![],
+            results: vec![],
+        }
+    }
+}
+
+impl Sarif {
+    /// Set the name of the tool that reports the diagnostics, `ceport` by default.
+    pub fn with_tool_name<N>(mut self, name: N) -> Self
+    where
+        Cow<'static, str>: From<N>,
+    {
+        self.tool_name = name.into();
+        self
+    }
+
+    /// Returns the SARIF log of the diagnostics rendered since the last call, in one run.
+    ///
+    /// Every code of the diagnostics is described by one rule of the tool, with the code url
+    /// as its `helpUri`.
+    pub fn finish(&mut self) -> Value {
+        let mut driver = Map::new();
+
+        driver.insert("name".into(), self.tool_name.clone().into());
+
+        if !self.rules.is_empty() {
+            driver.insert("rules".into(), std::mem::take(&mut self.rules).into());
+        }
+
+        json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": driver,
+                },
+                "columnKind": "utf16CodeUnits",
+                "results": std::mem::take(&mut self.results),
+            }],
+        })
+    }
+}
+
+/// Convert `diagnostic` into a SARIF `result` object.
+pub fn to_result<F>(
+    files: &F,
+    diagnostic: &Diagnostic<'_>,
+) -> std::result::Result<Value, FilesError>
+where
+    F: Files,
+{
+    let mut result = Map::new();
+
+    if let Some(code) = &diagnostic.code {
+        result.insert("ruleId".into(), code.to_string().into());
+    }
+
+    let level = match diagnostic.level {
+        Level::Bug | Level::Error => "error",
+        Level::Warning => "warning",
+        Level::Note | Level::Help => "note",
+    };
+
+    result.insert("level".into(), level.into());
+
+    let mut text = diagnostic.message.to_string();
+
+    for note in diagnostic.nodes.iter().chain(&diagnostic.helps) {
+        text.push('\n');
+        text.push_str(note);
+    }
+
+    result.insert("message".into(), json!({ "text": text }));
+
+    let mut locations = vec![];
+
+    for label in &diagnostic.labels {
+        let location = files.to_location(label.id, &label.primary.byte_range(files, label.id)?)?;
+
+        locations.push(json!({
+            "physicalLocation": {
+                "artifactLocation": {
+                    "uri": files.to_file_name(label.id)?,
+                },
+                "region": to_region(files, label.id, &location),
+            },
+            "message": {
+                "text": label.primary.message,
+            },
+        }));
+    }
+
+    result.insert("locations".into(), locations.into());
+
+    let mut related_locations = vec![];
+
+    for label in &diagnostic.labels {
+        for region in &label.secondary {
+            let location = files.to_location(label.id, &region.byte_range(files, label.id)?)?;
+
+            related_locations.push(json!({
+                "id": related_locations.len(),
+                "physicalLocation": {
+                    "artifactLocation": {
+                        "uri": files.to_file_name(label.id)?,
+                    },
+                    "region": to_region(files, label.id, &location),
+                },
+                "message": {
+                    "text": region.message,
+                },
+            }));
+        }
+    }
+
+    if !related_locations.is_empty() {
+        result.insert("relatedLocations".into(), related_locations.into());
+    }
+
+    if !diagnostic.metadata.is_empty() {
+        let properties = diagnostic
+            .metadata
+            .iter()
+            .map(|(key, value)| (key.to_string(), Value::from(value.as_ref())))
+            .collect::<Map<_, _>>();
+
+        result.insert("properties".into(), properties.into());
+    }
+
+    Ok(result.into())
+}
+
+/// Convert `location` into a SARIF `region`, with columns counted in UTF-16 code units as
+/// declared by the `columnKind` of the run.
+fn to_region<F>(files: &F, id: FileId, location: &Range<Location>) -> Value
+where
+    F: Files,
+{
+    let column = |location: Location| {
+        let line = files.try_line(id, location.lines).unwrap_or("");
+
+        let prefix = line.get(..location.cols - 1).unwrap_or(line);
+
+        prefix.encode_utf16().count() + 1
+    };
+
+    json!({
+        "startLine": location.start.lines,
+        "startColumn": column(location.start),
+        "endLine": location.end.lines,
+        "endColumn": column(location.end),
+    })
+}
+
+/// Convert the code of `diagnostic` into a SARIF `reportingDescriptor`, with the code url as
+/// its `helpUri`.
+fn to_rule(diagnostic: &Diagnostic<'_>) -> Option<Value> {
+    let code = diagnostic.code.as_ref()?;
+
+    let mut rule = Map::new();
+
+    rule.insert("id".into(), code.to_string().into());
+
+    if let Some(url) = &diagnostic.url {
+        rule.insert("helpUri".into(), url.as_ref().into());
+    }
+
+    Some(rule.into())
+}
+
+impl Renderer for Sarif {
+    type Error = std::io::Error;
+
+    fn render<'a, F, D>(&mut self, files: &F, diagnostic: D) -> Result<()>
+    where
+        F: Files,
+        Diagnostic<'a>: From<D>,
+    {
+        self.render_ref(files, &diagnostic.into())
+    }
+
+    fn render_ref<F>(&mut self, files: &F, diagnostic: &Diagnostic<'_>) -> Result<()>
+    where
+        F: Files,
+    {
+        check_files(files, diagnostic)?;
+
+        self.results.push(to_result(files, diagnostic)?);
+
+        if let Some(rule) = to_rule(diagnostic)
+            && !self.rules.iter().any(|known| known["id"] == rule["id"])
+        {
+            self.rules.push(rule);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{Diagnostic, Label, Renderer, SourceCodes};
+
+    use super::Sarif;
+
+    #[test]
+    fn test_sarif() {
+        let mut files = SourceCodes::default();
+
+        files.add("src/main.rs", "fn main() {\n    let x = 1\n}\n");
+
+        let mut sarif = Sarif::default().with_tool_name("mytool");
+
+        sarif
+            .render_all(
+                &files,
+                [
+                    Diagnostic::error("expected `;`")
+                        .with_code("E0308")
+                        .with_code_url("https://example.com/E0308")
+                        .with_label(
+                            Label::new(0, 16..25, "missing `;`")
+                                .with_secondary(26..27, "before this"),
+                        )
+                        .with_note("statements end with `;`")
+                        .with_metadata("category", "syntax"),
+                    Diagnostic::warning("unused variable")
+                        .with_code("E0308")
+                        .with_label(Label::new(0, 20..21, "never read")),
+                    Diagnostic::help("add a semicolon"),
+                ],
+            )
+            .unwrap();
+
+        let golden = json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "mytool",
+                        "rules": [{ "id": "E0308", "helpUri": "https://example.com/E0308" }]
+                    }
+                },
+                "columnKind": "utf16CodeUnits",
+                "results": [
+                    {
+                        "ruleId": "E0308",
+                        "level": "error",
+                        "message": { "text": "expected `;`\nstatements end with `;`" },
+                        "locations": [{
+                            "physicalLocation": {
+                                "artifactLocation": { "uri": "src/main.rs" },
+                                "region": {
+                                    "startLine": 2,
+                                    "startColumn": 5,
+                                    "endLine": 2,
+                                    "endColumn": 14
+                                }
+                            },
+                            "message": { "text": "missing `;`" }
+                        }],
+                        "relatedLocations": [{
+                            "id": 0,
+                            "physicalLocation": {
+                                "artifactLocation": { "uri": "src/main.rs" },
+                                "region": {
+                                    "startLine": 3,
+                                    "startColumn": 1,
+                                    "endLine": 3,
+                                    "endColumn": 2
+                                }
+                            },
+                            "message": { "text": "before this" }
+                        }],
+                        "properties": { "category": "syntax" }
+                    },
+                    {
+                        "ruleId": "E0308",
+                        "level": "warning",
+                        "message": { "text": "unused variable" },
+                        "locations": [{
+                            "physicalLocation": {
+                                "artifactLocation": { "uri": "src/main.rs" },
+                                "region": {
+                                    "startLine": 2,
+                                    "startColumn": 9,
+                                    "endLine": 2,
+                                    "endColumn": 10
+                                }
+                            },
+                            "message": { "text": "never read" }
+                        }]
+                    },
+                    {
+                        "level": "note",
+                        "message": { "text": "add a semicolon" },
+                        "locations": []
+                    }
+                ]
+            }]
+        });
+
+        assert_eq!(sarif.finish(), golden);
+
+        // results and rules are taken by `finish`.
+        let next = sarif.finish();
+
+        assert_eq!(next["runs"][0]["results"], json!([]));
+        assert_eq!(
+            next["runs"][0]["tool"]["driver"],
+            json!({ "name": "mytool" })
+        );
+    }
+
+    #[test]
+    fn test_sarif_columns() {
+        let mut files = SourceCodes::default();
+
+        files.add("src/main.rs", "let s = \"é😀\"; x\n");
+
+        let mut sarif = Sarif::default();
+
+        sarif
+            .render(
+                &files,
+                Diagnostic::error("unresolved name").with_label(Label::new(0, 18..19, "not found")),
+            )
+            .unwrap();
+
+        let log = sarif.finish();
+
+        // `é` is 2 bytes and 1 UTF-16 code unit, `😀` is 4 bytes and 2 code units.
+        assert_eq!(
+            log["runs"][0]["results"][0]["locations"][0]["physicalLocation"]["region"],
+            json!({ "startLine": 1, "startColumn": 16, "endLine": 1, "endColumn": 17 })
+        );
+    }
+}
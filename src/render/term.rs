@@ -1,320 +1,306 @@
 //! A terminal renderer implementation.
 
-use std::{
-    collections::{HashMap, HashSet},
-    io::{Result, Write},
-};
+use std::io::{IsTerminal, Result};
+
+use termcolor::{Buffer, BufferWriter, ColorChoice, StandardStream, WriteColor};
 
-use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
-use unicode_width::UnicodeWidthStr;
+use crate::{Code, Diagnostic, Label};
 
-use crate::{Diagnostic, Label, Level};
+use super::{
+    CodeExplanations, Files, Renderer, Theme,
+    layout::{Colored, Layout},
+};
 
-use super::{Files, Renderer};
+pub use super::layout::{CharSet, PathStyle, Verbosity, underline_width};
 
 /// A diagnostic reporting renderer implementation that renders the result to the terminal.
-pub struct Term(StandardStream);
+///
+/// The layout is shared with [`Plain`](super::plain::Plain), which writes it without escape
+/// sequences.
+pub struct Term<W = StandardStream> {
+    layout: Layout<Colored<W>>,
+}
 
 impl Default for Term {
     fn default() -> Self {
-        Self(StandardStream::stdout(ColorChoice::Always))
+        Self::with_color(ColorChoice::Auto)
     }
 }
 
 impl Term {
-    fn error_color(&mut self) -> Result<()> {
-        self.0
-            .set_color(ColorSpec::new().set_bold(true).set_fg(Some(Color::Red)))
+    /// Create a new `Term` that renders to stdout with the given color `choice`.
+    ///
+    /// [`ColorChoice::Auto`] honors `NO_COLOR` and `TERM=dumb`, and disables color
+    /// when stdout is not a terminal.
+    ///
+    /// Messages are wrapped to the width of the terminal, if it can be detected.
+    pub fn with_color(choice: ColorChoice) -> Self {
+        let choice = resolve_color(choice, std::io::stdout().is_terminal());
+
+        let mut term = Self::with_writer(StandardStream::stdout(choice));
+
+        term.layout.width = terminal_size::terminal_size().map(|(width, _)| width.0 as usize);
+
+        term
     }
+}
 
-    fn bug_color(&mut self) -> Result<()> {
-        self.0
-            .set_color(ColorSpec::new().set_bold(true).set_fg(Some(Color::Magenta)))
+/// Downgrade [`ColorChoice::Auto`] to [`ColorChoice::Never`] when the output is not a terminal.
+fn resolve_color(choice: ColorChoice, is_terminal: bool) -> ColorChoice {
+    match choice {
+        ColorChoice::Auto if !is_terminal => ColorChoice::Never,
+        choice => choice,
     }
+}
+
+impl Term<Buffer> {
+    /// Render `diagnostic` into a byte buffer, with ANSI escapes if `choice` enables color,
+    /// e.g. to embed the output in a protocol frame.
+    ///
+    /// [`ColorChoice::Auto`] enables color as if writing to stdout.
+    pub fn render_to_vec<'a, F, D>(files: &F, diagnostic: D, choice: ColorChoice) -> Result<Vec<u8>>
+    where
+        F: Files,
+        Diagnostic<'a>: From<D>,
+    {
+        let mut term = Term::with_writer(BufferWriter::stdout(choice).buffer());
+
+        term.render(files, diagnostic)?;
 
-    fn warn_color(&mut self) -> Result<()> {
-        self.0
-            .set_color(ColorSpec::new().set_bold(true).set_fg(Some(Color::Yellow)))
+        Ok(term.into_inner().into_inner())
     }
+}
 
-    fn text_color(&mut self) -> Result<()> {
-        self.0
-            .set_color(ColorSpec::new().set_bold(true).set_fg(Some(Color::White)))
+impl<W> Term<W>
+where
+    W: WriteColor,
+{
+    /// Create a new `Term` that renders into `writer`.
+    pub fn with_writer(writer: W) -> Self {
+        Self {
+            layout: Layout::with_writer(Colored::new(writer)),
+        }
     }
 
-    fn help_color(&mut self) -> Result<()> {
-        self.0.set_color(
-            ColorSpec::new()
-                .set_bold(true)
-                .set_fg(Some(Color::Ansi256(255))),
-        )
+    /// Set the minimum width of the line number gutter, so that blocks of small files
+    /// align with the blocks of larger ones.
+    pub fn with_min_gutter_width(mut self, width: usize) -> Self {
+        self.layout.min_gutter_width = width;
+        self
     }
 
-    fn label_color(&mut self) -> Result<()> {
-        self.0.set_color(ColorSpec::new().set_fg(Some(Color::Blue)))
+    /// Set whether [`render_all`](Renderer::render_all) merges diagnostics whose labels are all
+    /// in the same file into a single file block, instead of repeating the file header and the
+    /// source lines for each one.
+    pub fn with_coalesce(mut self, coalesce: bool) -> Self {
+        self.layout.coalesce = coalesce;
+        self
     }
 
-    fn code_color(&mut self) -> Result<()> {
-        self.0
-            .set_color(ColorSpec::new().set_fg(Some(Color::White)))
+    /// Set the visual style of the output.
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.layout.theme = theme;
+        self
     }
 
-    fn primary_color(&mut self) -> Result<()> {
-        self.0.set_color(ColorSpec::new().set_fg(Some(Color::Red)))
+    /// Consume this `Term`, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.layout.into_inner().into_inner()
     }
 
-    fn write_level(&mut self, level: Level) -> Result<()> {
-        match level {
-            Level::Bug => {
-                self.bug_color()?;
+    /// Set the maximum number of lines a multiline region may span before it is rendered
+    /// in compact form: only its first and last lines are shown, joined by an elision row.
+    pub fn with_multiline_threshold(mut self, lines: usize) -> Self {
+        self.layout.multiline_threshold = lines;
+        self
+    }
 
-                write!(&mut self.0, "  bug")?;
-            }
-            Level::Error => {
-                self.error_color()?;
+    /// Set whether identical notes of a diagnostic are rendered only once, which is the default.
+    pub fn with_dedup_notes(mut self, dedup: bool) -> Self {
+        self.layout.dedup_notes = dedup;
+        self
+    }
 
-                write!(&mut self.0, "error")?;
-            }
-            Level::Warning => {
-                self.warn_color()?;
+    /// Set whether source lines containing right-to-left text are wrapped in bidi isolate
+    /// controls, so that terminals keep their characters in logical order under the carets.
+    pub fn with_bidi_isolation(mut self, isolate: bool) -> Self {
+        self.layout.bidi_isolation = isolate;
+        self
+    }
 
-                write!(&mut self.0, " warn")?;
-            }
-            Level::Note => {
-                self.text_color()?;
+    /// Set whether diagnostics that fail [`validate`](Diagnostic::validate) are replaced by an
+    /// internal error [`Bug`](crate::Level::Bug) diagnostic, instead of failing or being patched up.
+    ///
+    /// This is meant for development, to catch span bugs of a compiler.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.layout.strict = strict;
+        self
+    }
 
-                write!(&mut self.0, " note")?;
-            }
-            Level::Help => {
-                self.help_color()?;
+    /// Set how file names are shown in the header of file blocks, [`PathStyle::Full`] by default.
+    pub fn with_path_style(mut self, style: PathStyle) -> Self {
+        self.layout.path_style = style;
+        self
+    }
 
-                write!(&mut self.0, " help")?;
-            }
-        }
+    /// Set whether a ` --> file:line:col` line pointing at the primary label follows the header
+    /// of each diagnostic, which is the default.
+    pub fn with_locator(mut self, locator: bool) -> Self {
+        self.layout.locator = locator;
+        self
+    }
 
-        Ok(())
+    /// Set how much detail is rendered for each diagnostic, [`Verbosity::Normal`] by default.
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.layout.verbosity = verbosity;
+        self
     }
 
-    fn write_code(&mut self, code: usize) -> Result<()> {
-        write!(&mut self.0, "[{:06?}]", code)
+    /// Set the width in columns that label messages and notes are wrapped to.
+    ///
+    /// Without a width, which is the default for other writers than stdout, messages are not wrapped.
+    pub fn with_width(mut self, cols: usize) -> Self {
+        self.layout.width = Some(cols);
+        self
     }
 
-    fn write_header(&mut self, diagnostic: &Diagnostic) -> Result<()> {
-        self.write_level(diagnostic.level)?;
+    /// Set the number of unlabeled source lines shown before the first and after the last
+    /// labeled line of a file block, 0 by default.
+    pub fn with_context(mut self, lines: usize) -> Self {
+        self.layout.context = lines;
+        self
+    }
 
-        if let Some(code) = diagnostic.code {
-            self.write_code(code)?;
-        }
+    /// Set the characters of the gutter and the multiline connectors, [`CharSet::Unicode`] by default.
+    pub fn with_charset(mut self, charset: CharSet) -> Self {
+        self.layout.charset = charset;
+        self
+    }
 
-        self.text_color()?;
+    /// Set the registry of code explanations, so that diagnostics with a registered code point
+    /// to the command that explains it, and [`render_explanation`](Self::render_explanation)
+    /// can render it.
+    pub fn with_explanations(mut self, explanations: CodeExplanations) -> Self {
+        self.layout.explanations = Some(explanations);
+        self
+    }
 
-        writeln!(&mut self.0, ": {}", diagnostic.message)?;
+    /// Set whether the `[code]` of diagnostics is shown in the header, which is the default.
+    ///
+    /// This only affects the terminal output, structured renderers keep the code.
+    pub fn with_code_display(mut self, display: bool) -> Self {
+        self.layout.code_display = display;
+        self
+    }
 
-        Ok(())
+    /// Set whether the [`Stage`](crate::Stage) of diagnostics is shown in the header,
+    /// e.g. `error[000308] (parsing SVG): ...`, off by default.
+    pub fn with_stage_display(mut self, display: bool) -> Self {
+        self.layout.stage_display = display;
+        self
     }
 
-    fn write_notes(&mut self, prefix_width: usize, diagnostic: &Diagnostic) -> Result<()> {
-        for label in &diagnostic.nodes {
-            self.label_color()?;
-            write!(&mut self.0, "{} =", " ".repeat(prefix_width))?;
-            self.code_color()?;
-            writeln!(&mut self.0, " {}", label)?;
-        }
+    /// Set the display columns that tabs in the source expand to, e.g. `[8, 16, 24]`.
+    ///
+    /// Past the last stop, and by default, tabs expand to uniform stops every
+    /// [tab width](Self::with_tab_width) columns.
+    pub fn with_tab_stops<I>(mut self, stops: I) -> Self
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        self.layout.set_tab_stops(stops);
+        self
+    }
 
-        Ok(())
+    /// Set the distance between uniform tab stops, 4 columns by default.
+    ///
+    /// A width of 0 is treated as 1.
+    pub fn with_tab_width(mut self, width: usize) -> Self {
+        self.layout.set_tab_width(width);
+        self
     }
 
-    fn write_snippets<F>(&mut self, files: &F, diagnostic: &Diagnostic) -> Result<()>
+    /// Render the long-form explanation of `code` from the registry set by
+    /// [`with_explanations`](Self::with_explanations).
+    ///
+    /// Markdown headings are rendered in the header style and fenced code blocks are indented.
+    /// Returns false, rendering nothing, if `code` has no registered explanation.
+    pub fn render_explanation<'c, C>(&mut self, code: C) -> Result<bool>
     where
-        F: Files,
+        Code<'c>: From<C>,
     {
-        for label in &diagnostic.labels {
-            let prefix_width = self.write_file_snippet(files, label)?;
-            self.write_notes(prefix_width, diagnostic)?;
-        }
-
-        Ok(())
+        self.layout.render_explanation(code)
     }
 
-    fn write_file_snippet<'a, F>(&mut self, files: &F, label: &Label<'a>) -> Result<usize>
+    /// Render a single label standalone: its file block and underlines, without the diagnostic
+    /// header and notes. The primary region is styled as an [`Error`](crate::Level::Error).
+    pub fn render_label<F>(&mut self, files: &F, label: &Label<'_>) -> Result<()>
     where
         F: Files,
     {
-        let mut lines = HashSet::new();
-        let mut inline_labels = HashMap::new();
-        let mut multiline_lables = HashMap::new();
-        let mut multilines = 0usize;
-
-        let location = files.to_location(label.id, &label.primary.range);
-
-        let mut max_lines = location.end.lines;
-
-        lines.insert(location.start.lines);
-        lines.insert(location.end.lines);
-
-        if location.start.lines == location.end.lines {
-            inline_labels.insert(
-                location.start.lines,
-                (location, &label.primary.message, true),
-            );
-        } else {
-            multiline_lables
-                .entry(location.start.lines)
-                .or_insert(vec![])
-                .push((location.start.cols, None, multilines));
-
-            multiline_lables
-                .entry(location.start.lines)
-                .or_insert(vec![])
-                .push((location.end.cols, Some(&label.primary.message), multilines));
-
-            multilines += 1;
-        }
-
-        for region in &label.secondary {
-            let location = files.to_location(label.id, &region.range);
-
-            if location.end.lines > max_lines {
-                max_lines = location.end.lines;
-            }
-
-            lines.insert(location.start.lines);
-            lines.insert(location.end.lines);
-
-            if location.start.lines == location.end.lines {
-                inline_labels.insert(location.start.lines, (location, &region.message, false));
-            } else {
-                multiline_lables
-                    .entry(location.start.lines)
-                    .or_insert(vec![])
-                    .push((location.start.cols, None, multilines));
-
-                multiline_lables
-                    .entry(location.end.lines)
-                    .or_insert(vec![])
-                    .push((location.end.cols, Some(&region.message), multilines));
-
-                multilines += 1;
-            }
-        }
-
-        let prefix_width = max_lines.to_string().len();
-
-        let mut lines = lines.drain().collect::<Vec<_>>();
-
-        lines.sort();
-
-        self.label_color()?;
-
-        writeln!(
-            &mut self.0,
-            "{} ┌─ {}",
-            " ".repeat(prefix_width),
-            files.to_file_name(label.id)
-        )?;
-
-        let ident_size = multilines + 1;
-
-        for line in lines {
-            self.label_color()?;
-            write!(&mut self.0, "{:>width$} │", line, width = prefix_width)?;
-            self.code_color()?;
-            let line_content = files.as_str(label.id, line);
-            writeln!(
-                &mut self.0,
-                "{}{}",
-                " ".repeat(ident_size * 2),
-                line_content
-            )?;
-
-            if let Some(multilines) = multiline_lables.get(&line) {
-                self.label_color()?;
-
-                for (offset, label, index) in multilines {
-                    if label.is_none() {
-                        write!(&mut self.0, "{} │", " ".repeat(prefix_width))?;
-                        writeln!(
-                            &mut self.0,
-                            "{}╭{}'",
-                            " ".repeat(*index * 2 + 1),
-                            "─".repeat(*offset + ident_size - *index * 2 - 4)
-                        )?;
-                    }
-                }
-            }
-
-            if let Some((location, message, primary)) = inline_labels.get(&line) {
-                self.label_color()?;
-                write!(&mut self.0, "{} │", " ".repeat(prefix_width))?;
-
-                let prefix = UnicodeWidthStr::width(&line_content[..location.start.cols - 1]);
-
-                let content = UnicodeWidthStr::width(
-                    &line_content[location.start.cols - 1..location.end.cols - 1],
-                );
-
-                write!(&mut self.0, "{}", " ".repeat(prefix + ident_size))?;
-
-                if *primary {
-                    self.primary_color()?;
-                    write!(&mut self.0, "{}", "^".repeat(content))?;
-                } else {
-                    write!(&mut self.0, "{}", "-".repeat(content))?;
-                }
-
-                writeln!(&mut self.0, " {}", message)?;
-            }
-
-            if let Some(multilines) = multiline_lables.get(&line) {
-                self.label_color()?;
-
-                for (offset, label, index) in multilines {
-                    if let Some(label) = label {
-                        write!(&mut self.0, "{} │", " ".repeat(prefix_width))?;
-                        writeln!(
-                            &mut self.0,
-                            "{}╰{}^ {}",
-                            " ".repeat(*index * 2 + 1),
-                            "─".repeat(*offset + ident_size - *index * 2 - 4),
-                            label
-                        )?;
-                    }
-                }
-            }
-        }
-
-        Ok(prefix_width)
+        self.layout.render_label(files, label)
     }
 }
 
-impl Renderer for Term {
+impl<W> Renderer for Term<W>
+where
+    W: WriteColor,
+{
     type Error = std::io::Error;
 
     fn render<'a, F, D>(&mut self, files: &F, diagnostic: D) -> Result<()>
     where
         F: Files,
-        crate::Diagnostic<'a>: From<D>,
+        Diagnostic<'a>: From<D>,
     {
-        let diagnostic: Diagnostic<'a> = diagnostic.into();
+        self.layout.render(files, diagnostic)
+    }
 
-        self.write_header(&diagnostic)?;
+    fn render_ref<F>(&mut self, files: &F, diagnostic: &Diagnostic<'_>) -> Result<()>
+    where
+        F: Files,
+    {
+        self.layout.render_ref(files, diagnostic)
+    }
 
-        self.write_snippets(files, &diagnostic)?;
+    fn render_all<'a, F, I, D>(&mut self, files: &F, diagnostics: I) -> Result<()>
+    where
+        F: Files,
+        I: IntoIterator<Item = D>,
+        Diagnostic<'a>: From<D>,
+    {
+        self.layout.render_all(files, diagnostics)
+    }
 
-        Ok(())
+    fn render_section<'a, F, I, D>(&mut self, title: &str, files: &F, diagnostics: I) -> Result<()>
+    where
+        F: Files,
+        I: IntoIterator<Item = D>,
+        Diagnostic<'a>: From<D>,
+    {
+        self.layout.render_section(title, files, diagnostics)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Diagnostic, Label, Renderer, SourceCodes};
+    use std::{
+        borrow::Cow,
+        cell::Cell,
+        io::{self, Write},
+        rc::Rc,
+    };
 
-    use super::Term;
+    use termcolor::{Ansi, Color, ColorChoice, ColorSpec, NoColor, WriteColor};
 
-    #[test]
-    fn test_term() {
-        let mut term = Term::default();
+    use crate::{
+        Applicability, CodeExplanations, Diagnostic, DynRenderer, Label, Level, Location, Renderer,
+        SourceCodes, Stage, Theme, Underline, render_stream,
+    };
+
+    use super::{CharSet, PathStyle, Term, Verbosity, resolve_color, underline_width};
 
+    fn fizz_buzz() -> SourceCodes<'static> {
         let mut files = SourceCodes::default();
 
         files.add(
@@ -341,6 +327,23 @@ mod tests {
             ),
         );
 
+        files
+    }
+
+    fn render_to_string<'a>(files: &SourceCodes, diagnostic: Diagnostic<'a>) -> String {
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        term.render(files, diagnostic).unwrap();
+
+        String::from_utf8(term.into_inner().into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_term() {
+        let mut term = Term::with_writer(Ansi::new(vec![]));
+
+        let files = fizz_buzz();
+
         term.render(
             &files,
             Diagnostic::bug("`case` clauses have incompatible types")
@@ -362,5 +365,1669 @@ mod tests {
                 )),
         )
         .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(output.starts_with("\x1b[0m\x1b[1m\x1b[35m  bug[000010]"));
+        assert!(output.contains("`case` clauses have incompatible types\n"));
+        assert!(output.contains("FizzBuzz,fun:16:16"));
+        assert!(output.contains("\x1b[31m^^^ expected `String`, found `Nat`"));
+        assert!(output.contains(" expected type `String`\n        found type `Nat`\n"));
+    }
+
+    #[test]
+    fn test_insertion_order() {
+        let files = fizz_buzz();
+
+        let secondary = [
+            (211..331, "`case` clauses have incompatible types"),
+            (258..268, "expected type `String` found here"),
+            (258..331, "this is found to be of type `String`"),
+            (284..290, "this is found to be of type `String`"),
+            (306..312, "this is found to be of type `String`"),
+            (186..192, "expected type `String` found here"),
+        ];
+
+        let forward = secondary.iter().cloned().fold(
+            Label::new(0, 328..331, "expected `String`, found `Nat`"),
+            |label, (range, message)| label.with_secondary(range, message),
+        );
+
+        let backward = secondary.iter().rev().cloned().fold(
+            Label::new(0, 328..331, "expected `String`, found `Nat`"),
+            |label, (range, message)| label.with_secondary(range, message),
+        );
+
+        let forward = render_to_string(
+            &files,
+            Diagnostic::error("incompatible types")
+                .with_label(forward)
+                .with_label(Label::new(0, 73..79, "first")),
+        );
+
+        let backward = render_to_string(
+            &files,
+            Diagnostic::error("incompatible types")
+                .with_label(Label::new(0, 73..79, "first"))
+                .with_label(backward),
+        );
+
+        assert_eq!(forward, backward);
+    }
+
+    #[test]
+    fn test_level_order() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("main.rs", "let x = 1;\n");
+
+        let error = || Label::new(id, 4..5, "an error").with_level(Level::Error);
+        let bug = || Label::new(id, 4..5, "a bug").with_level(Level::Bug);
+        let note = || Label::new(id, 4..5, "a note").with_level(Level::Note);
+
+        let forward = render_to_string(
+            &files,
+            Diagnostic::error("levels")
+                .with_label(error())
+                .with_label(bug())
+                .with_label(note()),
+        );
+
+        let backward = render_to_string(
+            &files,
+            Diagnostic::error("levels")
+                .with_label(note())
+                .with_label(bug())
+                .with_label(error()),
+        );
+
+        assert_eq!(forward, backward);
+
+        // regions at the same place are stacked by severity, not by the name of their level.
+        let note = forward.find("a note").unwrap();
+        let error = forward.find("an error").unwrap();
+        let bug = forward.find("a bug").unwrap();
+
+        assert!(note < error && error < bug);
+    }
+
+    #[test]
+    fn test_compact_multiline() {
+        let mut files = SourceCodes::default();
+
+        let content = (1..=40)
+            .map(|line| format!("line{:02}", line))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        files.add("lines.txt", &content);
+
+        // from `line02` to `line31`.
+        let diagnostic =
+            || Diagnostic::error("tall span").with_label(Label::new(0, 7..7 * 30 + 6, "region"));
+
+        let compact = render_to_string(&files, diagnostic());
+
+        assert!(compact.contains(" 2 │    line02"));
+        assert!(compact.contains("   ┆ │\n31 │ │  line31"));
+        assert!(!compact.contains("line03"));
+        assert!(!compact.contains("line30"));
+        assert_eq!(compact.matches('┆').count(), 1);
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_multiline_threshold(30);
+
+        term.render(&files, diagnostic()).unwrap();
+
+        let full = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        for line in 2..=31 {
+            assert!(full.contains(&format!("line{:02}", line)));
+        }
+
+        assert!(!full.contains('┆'));
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn test_reversed_range() {
+        let files = fizz_buzz();
+
+        let reversed = render_to_string(
+            &files,
+            Diagnostic::error("incompatible types").with_label(Label::new(
+                0,
+                331..328,
+                "expected `String`, found `Nat`",
+            )),
+        );
+
+        let ordered = render_to_string(
+            &files,
+            Diagnostic::error("incompatible types").with_label(Label::new(
+                0,
+                328..331,
+                "expected `String`, found `Nat`",
+            )),
+        );
+
+        assert_eq!(reversed, ordered);
+        assert!(reversed.contains("^^^ expected `String`, found `Nat`"));
+    }
+
+    #[test]
+    fn test_suggestion_marker() {
+        let files = fizz_buzz();
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("incompatible types").with_label(
+                Label::new(0, 328..331, "expected `String`, found `Nat`")
+                    .with_secondary(306..312, "this is found to be of type `String`")
+                    .with_suggestion(328..331, "show num"),
+            ),
+        );
+
+        assert!(output.contains("16~│"));
+        assert_eq!(output.matches("~│").count(), 1);
+        assert!(output.contains("15 │"));
+        assert!(output.ends_with("   = help: replace `num` with `show num`\n"));
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn test_suggestion_edits() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("main.rs", "fn main() {\n    let x = 1\n}\n");
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("expected `;`").with_label(
+                Label::new(id, 16..25, "missing `;`")
+                    .with_applicable_suggestion(25..25, ";", Applicability::MachineApplicable)
+                    .with_suggestion(16..20, ""),
+            ),
+        );
+
+        assert!(output.ends_with("  = help: insert `;`\n  = help: remove `let `\n"));
+
+        // reversed ranges are swapped, like the ones of regions.
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("expected `;`")
+                .with_label(Label::new(id, 16..25, "missing `;`").with_suggestion(20..16, "")),
+        );
+
+        assert!(output.ends_with("  = help: remove `let `\n"));
+    }
+
+    #[test]
+    fn test_file_order() {
+        let mut files = SourceCodes::default();
+
+        files.add("a.txt", "// a\nalpha\n");
+        files.add("b.txt", "// b\nbeta\n");
+        files.add("c.txt", "// c\ngamma\n");
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("unknown symbols")
+                .with_label(Label::new(2, 5..10, "in c"))
+                .with_label(Label::new(0, 5..10, "in a")),
+        );
+
+        let a = output.find("┌─ a.txt").unwrap();
+        let c = output.find("┌─ c.txt").unwrap();
+
+        assert!(a < c);
+    }
+
+    /// A writer that records the buffer length at every `flush` call.
+    #[derive(Default)]
+    struct FlushRecorder {
+        buf: Vec<u8>,
+        flushes: Vec<usize>,
+    }
+
+    impl Write for FlushRecorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buf.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes.push(self.buf.len());
+            Ok(())
+        }
+    }
+
+    impl WriteColor for FlushRecorder {
+        fn supports_color(&self) -> bool {
+            false
+        }
+
+        fn set_color(&mut self, _: &ColorSpec) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn reset(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_flush_per_block() {
+        let mut files = SourceCodes::default();
+
+        files.add("a.txt", "// a\nalpha\n");
+        files.add("b.txt", "// b\nbeta\n");
+
+        let mut term = Term::with_writer(FlushRecorder::default());
+
+        term.render(
+            &files,
+            Diagnostic::error("unknown symbols")
+                .with_label(Label::new(0, 5..10, "in a"))
+                .with_label(Label::new(1, 5..9, "in b"))
+                .with_note("symbols must be declared before use"),
+        )
+        .unwrap();
+
+        let recorder = term.into_inner();
+
+        // header, file `a.txt`, file `b.txt`.
+        assert_eq!(recorder.flushes.len(), 3);
+        assert_eq!(recorder.flushes.last(), Some(&recorder.buf.len()));
+
+        for offset in recorder.flushes {
+            assert_eq!(recorder.buf[offset - 1], b'\n');
+        }
+    }
+
+    #[test]
+    fn test_warning_underline() {
+        let files = fizz_buzz();
+
+        let diagnostic = || {
+            Diagnostic::warning("unused binding").with_label(
+                Label::new(0, 328..331, "`num` is never formatted")
+                    .with_secondary(306..312, "this is a `String`"),
+            )
+        };
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_theme(Theme {
+            warning_underline: Underline {
+                primary: '~',
+                secondary: '-',
+            },
+            ..Default::default()
+        });
+
+        term.render(&files, diagnostic()).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(output.contains("~~~ `num` is never formatted"));
+        assert!(output.contains("------ this is a `String`"));
+
+        let output = render_to_string(&files, diagnostic());
+
+        assert!(output.contains("^^^ `num` is never formatted"));
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let files = fizz_buzz();
+
+        let diagnostics = || {
+            [
+                Diagnostic::error("incompatible types").with_label(Label::new(
+                    0,
+                    328..331,
+                    "expected `String`, found `Nat`",
+                )),
+                Diagnostic::warning("redundant pattern").with_label(Label::new(
+                    0,
+                    306..312,
+                    "this arm is never taken",
+                )),
+            ]
+        };
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_coalesce(true);
+
+        term.render_all(&files, diagnostics()).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(output.matches("┌─").count(), 1);
+        assert!(output.starts_with(
+            "error: incompatible types\n --> FizzBuzz,fun:16:16\n warn: redundant pattern\n --> FizzBuzz,fun:15:16\n"
+        ));
+        assert!(output.contains("^^^ expected `String`, found `Nat`"));
+        assert!(output.contains("^^^^^^ this arm is never taken"));
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        term.render_all(&files, diagnostics()).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(output.matches("┌─").count(), 2);
+    }
+
+    #[test]
+    fn test_empty_diagnostic() {
+        let files = SourceCodes::default();
+
+        let diagnostic = Diagnostic::error("could not compile `fizz_buzz`");
+
+        assert!(diagnostic.is_empty());
+
+        assert_eq!(
+            render_to_string(&files, diagnostic),
+            "error: could not compile `fizz_buzz`\n"
+        );
+
+        assert_eq!(
+            render_to_string(
+                &files,
+                Diagnostic::error("aborting").with_note("2 errors emitted")
+            ),
+            "error: aborting\n = note: 2 errors emitted\n"
+        );
+    }
+
+    #[test]
+    fn test_missing_sources() {
+        let files = SourceCodes::default();
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        let error = term
+            .render(
+                &files,
+                Diagnostic::error("incompatible types").with_label(Label::new(0, 0..1, "here")),
+            )
+            .unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            error.to_string(),
+            "diagnostic references file id 0 but no such source was provided"
+        );
+    }
+
+    #[test]
+    fn test_offset_out_of_range() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("main.rs", "fn main() {}\n");
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        let error = term
+            .render(
+                &files,
+                Diagnostic::error("unexpected end of file").with_label(Label::new(
+                    id,
+                    10..40,
+                    "here",
+                )),
+            )
+            .unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(error.to_string(), "offset 40 out of range in file id 0");
+    }
+
+    #[test]
+    fn test_min_gutter_width() {
+        let mut files = SourceCodes::default();
+
+        files.add("five.txt", "one\ntwo\nthree\nfour\nfive\n");
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_min_gutter_width(3);
+
+        term.render(
+            &files,
+            Diagnostic::error("unexpected word")
+                .with_label(Label::new(0, 8..13, "here"))
+                .with_note("expected a number"),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(
+            output,
+            "error: unexpected word\n --> five.txt:3:1\n    ┌─ five.txt\n  3 │  three\n    │  ^^^^^ here\n    = note: expected a number\n"
+        );
+    }
+
+    /// A writer whose color operations always fail.
+    #[derive(Default)]
+    struct BrokenColor(Vec<u8>);
+
+    impl Write for BrokenColor {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl WriteColor for BrokenColor {
+        fn supports_color(&self) -> bool {
+            true
+        }
+
+        fn set_color(&mut self, _: &ColorSpec) -> io::Result<()> {
+            Err(io::Error::other("color is not supported"))
+        }
+
+        fn reset(&mut self) -> io::Result<()> {
+            Err(io::Error::other("color is not supported"))
+        }
+    }
+
+    #[test]
+    fn test_color_fallback() {
+        let files = fizz_buzz();
+
+        let diagnostic = || {
+            Diagnostic::error("incompatible types")
+                .with_label(Label::new(0, 328..331, "expected `String`, found `Nat`"))
+                .with_note("expected type `String`")
+        };
+
+        let mut term = Term::with_writer(BrokenColor::default());
+
+        term.render(&files, diagnostic()).unwrap();
+
+        let output = String::from_utf8(term.into_inner().0).unwrap();
+
+        assert_eq!(output, render_to_string(&files, diagnostic()));
+    }
+
+    #[test]
+    fn test_location_label() {
+        let mut files = SourceCodes::default();
+
+        files.add("main.js", "let a = 1;\nlet b = 2;\nprint(c);\n");
+
+        let location = render_to_string(
+            &files,
+            Diagnostic::error("unknown function").with_label(Label::from_location(
+                0,
+                Location { lines: 3, cols: 1 },
+                Location { lines: 3, cols: 6 },
+                "not found in this scope",
+            )),
+        );
+
+        assert!(location.contains("3 │  print(c);\n  │  ^^^^^ not found in this scope"));
+
+        let range = render_to_string(
+            &files,
+            Diagnostic::error("unknown function").with_label(Label::new(
+                0,
+                22..27,
+                "not found in this scope",
+            )),
+        );
+
+        assert_eq!(location, range);
+    }
+
+    #[test]
+    fn test_dedup_notes() {
+        let files = fizz_buzz();
+
+        let diagnostic = || {
+            Diagnostic::error("incompatible types")
+                .with_label(Label::new(0, 328..331, "expected `String`, found `Nat`"))
+                .with_note("expected type `String`")
+                .with_note("found type `Nat`")
+                .with_note("expected type `String`")
+        };
+
+        let output = render_to_string(&files, diagnostic());
+
+        assert_eq!(output.matches("= note: expected type `String`").count(), 1);
+        assert!(
+            output.ends_with("   = note: expected type `String`\n   = note: found type `Nat`\n"),
+            "{}",
+            output
+        );
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_dedup_notes(false);
+
+        term.render(&files, diagnostic()).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(output.matches("= note: expected type `String`").count(), 2);
+    }
+
+    #[test]
+    fn test_render_label() {
+        let files = fizz_buzz();
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        term.render_label(
+            &files,
+            &Label::new(0, 328..331, "expected `String`, found `Nat`")
+                .with_secondary(258..331, "this is found to be of type `String`"),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(output.starts_with("   ┌─ FizzBuzz,fun\n"));
+        assert!(output.contains("^^^ expected `String`, found `Nat`"));
+        assert!(output.contains("│ │___________________- this is found to be of type `String`"));
+        assert!(!output.contains("error"));
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        term.render_label(&files, &Label::secondary(0, 306..312, "this is a `String`"))
+            .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        // a secondary-styled label is underlined as such, without any caret.
+        assert!(output.contains("------ this is a `String`"));
+        assert!(!output.contains('^'));
+    }
+
+    #[test]
+    fn test_underline_width() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("unicode.txt", "first line\nlet 名前 = \"café\";\n");
+
+        // `名前` is 6 bytes of two double-width chars.
+        let name = Label::new(id, 15..21, "name");
+        assert_eq!(underline_width(&files, &name.primary, id), Ok(4));
+
+        // `"café"` is 7 bytes of six single-width chars.
+        let literal = Label::new(id, 24..31, "literal");
+        assert_eq!(underline_width(&files, &literal.primary, id), Ok(6));
+    }
+
+    #[test]
+    fn test_header_separator() {
+        let files = fizz_buzz();
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_theme(Theme {
+            header_separator: " — ".into(),
+            ..Default::default()
+        });
+
+        term.render(&files, Diagnostic::error("mismatched types").with_code(308))
+            .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(output.starts_with("error[000308] — mismatched types\n"));
+    }
+
+    #[test]
+    fn test_stage_display() {
+        let files = SourceCodes::default();
+
+        let diagnostic = || {
+            Diagnostic::error("unclosed tag")
+                .with_code(12)
+                .with_stage(Stage::Parsing("SVG"))
+        };
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        term.render(&files, diagnostic()).unwrap();
+        term.render(
+            &files,
+            Diagnostic::warning("slow path").with_stage(Stage::Custom("lint")),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(output, "error[000012]: unclosed tag\n warn: slow path\n");
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_stage_display(true);
+
+        term.render(&files, diagnostic()).unwrap();
+        term.render(
+            &files,
+            Diagnostic::warning("slow path").with_stage(Stage::Custom("lint")),
+        )
+        .unwrap();
+        term.render(&files, Diagnostic::note("no stage")).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(
+            output,
+            "error[000012] (parsing SVG): unclosed tag\n warn (lint): slow path\n note: no stage\n"
+        );
+    }
+
+    #[test]
+    fn test_code_url() {
+        let files = SourceCodes::default();
+
+        let diagnostic = || {
+            Diagnostic::error("mismatched types")
+                .with_code("E0308")
+                .with_code_url("https://doc.rust-lang.org/error_codes/E0308.html")
+        };
+
+        let mut term = Term::with_writer(Ansi::new(vec![]));
+
+        term.render(&files, diagnostic()).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(output.contains(
+            "\x1b]8;;https://doc.rust-lang.org/error_codes/E0308.html\x1b\\[E0308]\x1b]8;;\x1b\\"
+        ));
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        term.render(&files, diagnostic()).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(output, "error[E0308]: mismatched types\n");
+    }
+
+    #[test]
+    fn test_string_code() {
+        let files = SourceCodes::default();
+
+        let mut explanations = CodeExplanations::default();
+
+        explanations.register("E0308", "Mismatched types");
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_explanations(explanations);
+
+        term.render(
+            &files,
+            Diagnostic::error("mismatched types").with_code("E0308"),
+        )
+        .unwrap();
+        term.render(
+            &files,
+            Diagnostic::warning("needless return")
+                .with_code(String::from("clippy::needless_return")),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(
+            output,
+            "error[E0308]: mismatched types\n = run `explain E0308` for details\n warn[clippy::needless_return]: needless return\n"
+        );
+    }
+
+    #[test]
+    fn test_tab_stops() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("table.tsv", "name\tage\tcity\nada\t36\tlondon\n");
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_tab_stops([8, 16, 24]);
+
+        term.render(
+            &files,
+            Diagnostic::error("invalid city").with_label(Label::new(id, 21..27, "unknown city")),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(output.contains("2 │  ada     36      london\n"));
+        assert!(output.contains("  │                  ^^^^^^ unknown city\n"));
+    }
+
+    #[test]
+    fn test_tab_width() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("main.c", "int main() {\n\t\treturn x;\n}\n");
+
+        let render = |mut term: Term<NoColor<Vec<u8>>>| {
+            term.render(
+                &files,
+                Diagnostic::error("undeclared identifier").with_label(Label::new(
+                    id,
+                    22..23,
+                    "not declared",
+                )),
+            )
+            .unwrap();
+
+            String::from_utf8(term.into_inner().into_inner()).unwrap()
+        };
+
+        let output = render(Term::with_writer(NoColor::new(vec![])));
+
+        assert!(output.contains(&format!("2 │{}return x;\n", " ".repeat(10))));
+        assert!(output.contains(&format!("  │{}^ not declared\n", " ".repeat(17))));
+
+        let output = render(Term::with_writer(NoColor::new(vec![])).with_tab_width(8));
+
+        assert!(output.contains(&format!("2 │{}return x;\n", " ".repeat(18))));
+        assert!(output.contains(&format!("  │{}^ not declared\n", " ".repeat(25))));
+    }
+
+    #[test]
+    fn test_primary_on_top() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("call.rs", "fn main() {\nlet x = foo(bar);\n}\n");
+
+        let mut term = Term::with_writer(Ansi::new(vec![]));
+
+        term.render(
+            &files,
+            Diagnostic::error("cannot find value `bar`").with_label(
+                Label::new(id, 24..27, "not found in this scope")
+                    .with_secondary(16..17, "bound here"),
+            ),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        let primary = output.find("\x1b[31m^^^ not found in this scope").unwrap();
+        let secondary = output.find("- bound here").unwrap();
+
+        assert!(primary < secondary);
+    }
+
+    #[test]
+    fn test_bidi_isolation() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("greeting.txt", "greetings\nlet s = \"مرحبا\";\n");
+
+        let diagnostic = || {
+            Diagnostic::warning("unused variable").with_label(Label::new(id, 14..15, "never read"))
+        };
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_bidi_isolation(true);
+
+        term.render(&files, diagnostic()).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(output.contains("\u{2066}let s = \"مرحبا\";\u{2069}\n"));
+
+        let output = render_to_string(&files, diagnostic());
+
+        assert!(!output.contains('\u{2066}'));
+    }
+
+    #[test]
+    fn test_render_until_first_error() {
+        let files = fizz_buzz();
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        let suppressed = term
+            .render_until_first_error(
+                &files,
+                [
+                    Diagnostic::warning("unused variable"),
+                    Diagnostic::error("mismatched types"),
+                    Diagnostic::error("unresolved name"),
+                ],
+            )
+            .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(suppressed);
+        assert!(output.contains("unused variable"));
+        assert!(output.contains("mismatched types"));
+        assert!(!output.contains("unresolved name"));
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        assert!(
+            !term
+                .render_until_first_error(&files, [Diagnostic::error("mismatched types")])
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_secondary_label() {
+        let files = fizz_buzz();
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("mismatched types")
+                .with_label(Label::primary(
+                    0,
+                    328..331,
+                    "expected `String`, found `Nat`",
+                ))
+                .with_label(Label::secondary(0, 306..312, "this is a `String`")),
+        );
+
+        assert!(output.contains("^^^ expected `String`, found `Nat`"));
+        assert!(output.contains("------ this is a `String`"));
+    }
+
+    #[test]
+    fn test_strict() {
+        let files = fizz_buzz();
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_strict(true);
+
+        term.render(
+            &files,
+            Diagnostic::error("mismatched types").with_label(Label::new(
+                1,
+                328..331,
+                "expected `String`, found `Nat`",
+            )),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(
+            output,
+            "  bug: internal error: invalid span\n = note: label references file id 1 but no such source was provided\n"
+        );
+    }
+
+    #[test]
+    fn test_path_style() {
+        let name = "/home/u/proj/src/main.rs";
+
+        assert_eq!(PathStyle::Full.apply(name), name);
+        assert_eq!(PathStyle::FileNameOnly.apply(name), "main.rs");
+
+        let mut files = SourceCodes::default();
+
+        let id = files.add(name, "fn main() {\n    let x = 1;\n}\n");
+
+        let mut term =
+            Term::with_writer(NoColor::new(vec![])).with_path_style(PathStyle::FileNameOnly);
+
+        term.render(
+            &files,
+            Diagnostic::warning("unused variable").with_label(Label::new(id, 20..21, "x")),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(output.contains("  ┌─ main.rs\n"));
+    }
+
+    #[test]
+    fn test_locator() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("src/main.rs", "fn main() {\n    let x = 1\n}\n");
+
+        let diagnostic = || {
+            Diagnostic::error("expected `;`")
+                .with_label(Label::secondary(id, 12..15, "in this statement"))
+                .with_label(Label::new(id, 20..25, "missing `;` after this"))
+        };
+
+        let output = render_to_string(&files, diagnostic());
+
+        assert!(
+            output.starts_with("error: expected `;`\n --> src/main.rs:2:9\n  ┌─ src/main.rs\n")
+        );
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_locator(false);
+
+        term.render(&files, diagnostic()).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(!output.contains("-->"));
+    }
+
+    /// A writer that records how many diagnostics were produced at every `flush` call.
+    struct StreamRecorder {
+        produced: Rc<Cell<usize>>,
+        flushes: Vec<usize>,
+    }
+
+    impl Write for StreamRecorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes.push(self.produced.get());
+            Ok(())
+        }
+    }
+
+    impl WriteColor for StreamRecorder {
+        fn supports_color(&self) -> bool {
+            false
+        }
+
+        fn set_color(&mut self, _: &ColorSpec) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn reset(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_render_stream() {
+        let files = fizz_buzz();
+
+        let produced = Rc::new(Cell::new(0));
+
+        let mut term = Term::with_writer(StreamRecorder {
+            produced: produced.clone(),
+            flushes: vec![],
+        });
+
+        let diagnostics = [
+            Diagnostic::error("mismatched types"),
+            Diagnostic::warning("unused variable"),
+            Diagnostic::error("unresolved name"),
+        ]
+        .into_iter()
+        .inspect(|_| produced.set(produced.get() + 1));
+
+        let counts = render_stream(&mut term, &files, diagnostics).unwrap();
+
+        assert_eq!(counts.errors, 2);
+        assert_eq!(counts.count(Level::Warning), 1);
+        assert_eq!(counts.total(), 3);
+
+        // each diagnostic is flushed before the next one is produced.
+        let flushes = term.into_inner().flushes;
+
+        assert_eq!(flushes.first(), Some(&1));
+        assert!(flushes.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(flushes.last(), Some(&3));
+    }
+
+    #[test]
+    fn test_line_label() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("five.txt", "one\ntwo\n\nfour\nfive is too long\n");
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::warning("line too long").with_label(Label::line(id, 5, "this line")),
+        );
+
+        assert!(output.contains("5 │  five is too long\n"));
+        assert!(output.contains("  │  ^^^^^^^^^^^^^^^^ this line\n"));
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::warning("empty line").with_label(Label::line(id, 3, "here")),
+        );
+
+        assert!(output.contains("  │  ^ here\n"));
+    }
+
+    #[test]
+    fn test_render_section() {
+        let files = fizz_buzz();
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        term.render_section(
+            "Diagnostics for target fizz",
+            &files,
+            [
+                Diagnostic::error("mismatched types"),
+                Diagnostic::warning("unused variable"),
+            ],
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(
+            output,
+            "=== Diagnostics for target fizz ===\nerror: mismatched types\n warn: unused variable\n"
+        );
+    }
+
+    #[test]
+    fn test_verbosity() {
+        let files = fizz_buzz();
+
+        let diagnostic = || {
+            Diagnostic::error("mismatched types")
+                .with_explained_code(308, "https://example.com/errors")
+                .with_label(
+                    Label::new(0, 328..331, "expected `String`, found `Nat`")
+                        .with_secondary(306..312, "this is a `String`"),
+                )
+                .with_note("expected type `String`")
+                .with_metadata("lint", "type-check")
+        };
+
+        let render = |verbosity| {
+            let mut term = Term::with_writer(NoColor::new(vec![])).with_verbosity(verbosity);
+
+            term.render(&files, diagnostic()).unwrap();
+
+            String::from_utf8(term.into_inner().into_inner()).unwrap()
+        };
+
+        let quiet = render(Verbosity::Quiet);
+
+        assert!(quiet.contains("^^^ expected `String`, found `Nat`"));
+        assert!(!quiet.contains("this is a `String`"));
+        assert!(!quiet.contains("= note: expected type `String`"));
+
+        let normal = render(Verbosity::Normal);
+
+        assert!(normal.contains("------ this is a `String`"));
+        assert!(normal.contains("= note: expected type `String`"));
+        assert!(!normal.contains("= lint: type-check"));
+        assert!(!normal.contains("https://example.com/errors/308"));
+
+        let verbose = render(Verbosity::Verbose);
+
+        assert!(verbose.contains("= note: expected type `String`"));
+        assert!(verbose.contains("= lint: type-check"));
+        assert!(verbose.contains("= for more information, see https://example.com/errors/308"));
+    }
+
+    #[test]
+    fn test_region_glyph() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("types.rs", "// types\nlet x: u8 = 1;\nlet y: u16 = x;\n");
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("mismatched types").with_label(
+                Label::new(id, 37..38, "expected `u16`, found `u8`")
+                    .with_secondary_glyph(16..18, "declared as `u8`", '~')
+                    .with_secondary_glyph(31..34, "expected due to this", '-'),
+            ),
+        );
+
+        assert!(output.contains("^ expected `u16`, found `u8`"));
+        assert!(output.contains("~~ declared as `u8`"));
+        assert!(output.contains("--- expected due to this"));
+    }
+
+    #[test]
+    fn test_render_to_vec() {
+        let files = fizz_buzz();
+
+        let diagnostic = || {
+            Diagnostic::error("mismatched types").with_label(Label::new(
+                0,
+                328..331,
+                "expected `String`, found `Nat`",
+            ))
+        };
+
+        let plain = Term::render_to_vec(&files, diagnostic(), ColorChoice::Never).unwrap();
+
+        assert!(
+            String::from_utf8(plain.clone())
+                .unwrap()
+                .contains("mismatched types")
+        );
+        assert!(!plain.contains(&0x1b));
+
+        let ansi = Term::render_to_vec(&files, diagnostic(), ColorChoice::AlwaysAnsi).unwrap();
+
+        assert!(
+            String::from_utf8(ansi.clone())
+                .unwrap()
+                .contains("mismatched types")
+        );
+        assert!(ansi.contains(&0x1b));
+    }
+
+    #[test]
+    fn test_notes_after_files() {
+        let mut files = SourceCodes::default();
+
+        let short = files.add("short.txt", "// a\nalpha\n");
+        let long = files.add("long.txt", "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11 beta\n");
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("unknown symbols")
+                .with_label(Label::new(short, 5..10, "in short"))
+                .with_label(Label::new(long, 24..28, "in long"))
+                .with_note("symbols must be declared before use"),
+        );
+
+        assert_eq!(
+            output
+                .matches("symbols must be declared before use")
+                .count(),
+            1
+        );
+        assert!(output.ends_with("^^^^ in long\n   = note: symbols must be declared before use\n"));
+    }
+
+    #[test]
+    fn test_notes_once() {
+        let files = fizz_buzz();
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("`match` arms have incompatible types")
+                .with_label(Label::new(0, 328..331, "expected `String`, found `Nat`"))
+                .with_label(Label::secondary(
+                    0,
+                    211..331,
+                    "`match` arms have incompatible types",
+                ))
+                .with_note("expected type `String`"),
+        );
+
+        assert_eq!(output.matches("expected type `String`").count(), 1);
+        assert!(output.ends_with("   = note: expected type `String`\n"));
+    }
+
+    #[test]
+    fn test_help_entries() {
+        let files = SourceCodes::default();
+
+        let diagnostic = || {
+            Diagnostic::error("aborting")
+                .with_help("try `--verbose`")
+                .with_note("2 errors emitted")
+        };
+
+        assert_eq!(
+            render_to_string(&files, diagnostic()),
+            "error: aborting\n = note: 2 errors emitted\n = help: try `--verbose`\n"
+        );
+
+        let mut term = Term::with_writer(Ansi::new(vec![]));
+
+        term.render(&files, diagnostic()).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        let theme = Theme::default();
+
+        let mut note = Ansi::new(vec![]);
+        note.set_color(
+            ColorSpec::new()
+                .set_bold(true)
+                .set_fg(Some(theme.note_color)),
+        )
+        .unwrap();
+        let note = String::from_utf8(note.into_inner()).unwrap();
+
+        let mut help = Ansi::new(vec![]);
+        help.set_color(
+            ColorSpec::new()
+                .set_bold(true)
+                .set_fg(Some(theme.help_color)),
+        )
+        .unwrap();
+        let help = String::from_utf8(help.into_inner()).unwrap();
+
+        assert!(output.contains(&format!("{}note:", note)));
+        assert!(output.contains(&format!("{}help:", help)));
+    }
+
+    #[test]
+    fn test_hidden_code() {
+        let files = SourceCodes::default();
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_code_display(false);
+
+        term.render(
+            &files,
+            Diagnostic::error("mismatched types")
+                .with_code(308)
+                .with_code_url("https://example.com/308"),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(output, "error: mismatched types\n");
+    }
+
+    #[test]
+    fn test_borrowed_diagnostic() {
+        let files = fizz_buzz();
+
+        let message = String::from("expected `String`, found `Nat`");
+        let note = String::from("expected type `String`");
+
+        let diagnostic = Diagnostic::error(message.as_str())
+            .with_label(Label::new(0, 328..331, message.as_str()))
+            .with_note(note.as_str());
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        // `render_ref` renders from a reference, without taking or cloning the diagnostic.
+        term.render_ref(&files, &diagnostic).unwrap();
+        DynRenderer::render_dyn(&mut term, &files, &diagnostic).unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(output.matches("= note: expected type `String`").count(), 2);
+        assert_eq!(
+            output,
+            format!("{0}{0}", diagnostic.to_plain_string(&files).unwrap())
+        );
+        assert!(matches!(diagnostic.message, Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_grouped_files() {
+        let mut files = SourceCodes::default();
+
+        let a = files.add("a.rs", "fn f() -> u8 {\n    g()\n}\n");
+        let b = files.add("b.rs", "pub fn g() -> String {\n    todo!()\n}\n");
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("mismatched types")
+                .with_label(Label::new(a, 19..22, "expected `u8`"))
+                .with_label(Label::secondary(b, 14..20, "found `String`"))
+                .with_label(Label::secondary(a, 10..12, "expected due to this")),
+        );
+
+        assert_eq!(output.matches("┌─").count(), 2);
+        assert!(output.contains(
+            "  ┌─ a.rs\n1 │  fn f() -> u8 {\n  │            -- expected due to this\n2 │      g()\n"
+        ));
+    }
+
+    #[test]
+    fn test_zero_width() {
+        let mut files = SourceCodes::default();
+
+        files.add("main.rs", "let x = 10\nlet y = 2;\n");
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("expected `;`").with_label(Label::new(0, 10..10, "expected `;`")),
+        );
+
+        let golden = [
+            "error: expected `;`",
+            " --> main.rs:1:11",
+            "  ┌─ main.rs",
+            "1 │  let x = 10",
+            "  │            ^ expected `;`",
+            "",
+        ];
+
+        assert_eq!(output, golden.join("\n"));
+
+        // an empty range at the start of a line, and one past the last line break.
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("unexpected end of file")
+                .with_label(Label::new(0, 22..22, "here"))
+                .with_label(Label::secondary(0, 11..11, "statement starts here")),
+        );
+
+        assert!(output.contains("2 │  let y = 2;\n  │  - statement starts here\n"));
+        assert!(output.contains("3 │  \n  │  ^ here\n"));
+    }
+
+    #[test]
+    fn test_explanations() {
+        let files = fizz_buzz();
+
+        let mut explanations = CodeExplanations::default().with_command("mytool explain");
+
+        explanations.register(
+            308,
+            "# Mismatched types\n\nThe expected type differs from the found one:\n\n```\nlet x: String = 1;\n```",
+        );
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_explanations(explanations);
+
+        term.render(&files, Diagnostic::error("mismatched types").with_code(308))
+            .unwrap();
+        term.render(&files, Diagnostic::error("unresolved name").with_code(425))
+            .unwrap();
+
+        assert!(term.render_explanation(308).unwrap());
+        assert!(!term.render_explanation(425).unwrap());
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert_eq!(
+            output,
+            "error[000308]: mismatched types\n = run `mytool explain 308` for details\nerror[000425]: unresolved name\n[000308]\nMismatched types\n\nThe expected type differs from the found one:\n\n    let x: String = 1;\n"
+        );
+    }
+
+    #[test]
+    fn test_multiline_boundaries() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add(
+            "main.rs",
+            "fn main() {\n    let x = foo(1,\n        2);\n}\n",
+        );
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("mismatched types").with_label(
+                Label::new(id, 24..40, "expected `u8`, found `()`")
+                    .with_secondary(20..40, "in this binding"),
+            ),
+        );
+
+        let golden = [
+            "error: mismatched types",
+            " --> main.rs:2:13",
+            "  ┌─ main.rs",
+            "2 │          let x = foo(1,",
+            "  │ _____________-",
+            "  │ │ _______________^",
+            "3 │ │ │          2);",
+            "  │ │____________- in this binding",
+            "  │   │__________^ expected `u8`, found `()`",
+            "",
+        ];
+
+        assert_eq!(output, golden.join("\n"));
+    }
+
+    #[test]
+    fn test_resolve_color() {
+        assert_eq!(resolve_color(ColorChoice::Auto, false), ColorChoice::Never);
+        assert_eq!(resolve_color(ColorChoice::Auto, true), ColorChoice::Auto);
+        assert_eq!(
+            resolve_color(ColorChoice::Always, false),
+            ColorChoice::Always
+        );
+        assert_eq!(resolve_color(ColorChoice::Never, true), ColorChoice::Never);
+    }
+
+    #[test]
+    fn test_ascii_charset() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add(
+            "main.rs",
+            "fn main() {\n    let x = foo(1,\n        2);\n\n\n    bar(x);\n}\n",
+        );
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_charset(CharSet::Ascii);
+
+        term.render(
+            &files,
+            Diagnostic::error("mismatched types").with_label(
+                Label::new(id, 24..40, "expected `u8`, found `()`")
+                    .with_secondary(49..52, "used here"),
+            ),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        let golden = [
+            "error: mismatched types",
+            " --> main.rs:2:13",
+            "  +- main.rs",
+            "2 |        let x = foo(1,",
+            "  | _______________^",
+            "3 | |          2);",
+            "  | |__________^ expected `u8`, found `()`",
+            "  :",
+            "6 |        bar(x);",
+            "  |        --- used here",
+            "",
+        ];
+
+        assert_eq!(output, golden.join("\n"));
+        assert!(output.is_ascii());
+    }
+
+    #[test]
+    fn test_theme_colors() {
+        let files = fizz_buzz();
+
+        let mut term = Term::with_writer(Ansi::new(vec![])).with_theme(Theme {
+            label_color: ColorSpec::new().set_fg(Some(Color::Cyan)).clone(),
+            primary_color: ColorSpec::new().set_fg(Some(Color::Green)).clone(),
+            ..Default::default()
+        });
+
+        term.render(
+            &files,
+            Diagnostic::error("unknown value").with_label(Label::new(0, 328..331, "not found")),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        assert!(output.contains("\x1b[36m   ┌─ FizzBuzz,fun"));
+        assert!(output.contains("\x1b[32m^^^ not found"));
+        assert!(!output.contains("\x1b[34m"));
+    }
+
+    #[test]
+    fn test_wrap_messages() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("main.rs", "fn main() {\n    let x = 1;\n}\n");
+
+        let mut term = Term::with_writer(NoColor::new(vec![])).with_width(32);
+
+        term.render(
+            &files,
+            Diagnostic::warning("unused variable")
+                .with_label(Label::new(
+                    id,
+                    20..21,
+                    "this variable is never read afterwards",
+                ))
+                .with_note("prefix it with an underscore to silence this warning"),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(term.into_inner().into_inner()).unwrap();
+
+        let golden = [
+            " warn: unused variable",
+            " --> main.rs:2:9",
+            "  ┌─ main.rs",
+            "2 │      let x = 1;",
+            "  │          ^ this variable is",
+            "  │          never read",
+            "  │          afterwards",
+            "  = note: prefix it with an",
+            "          underscore to silence",
+            "          this warning",
+            "",
+        ];
+
+        assert_eq!(output, golden.join("\n"));
+    }
+
+    #[test]
+    fn test_context() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add(
+            "main.rs",
+            "use std::io;\n\nfn main() {\n    let x = 1;\n    println!();\n}\n",
+        );
+
+        let render = |mut term: Term<NoColor<Vec<u8>>>| {
+            term.render(
+                &files,
+                Diagnostic::warning("unused variable").with_label(Label::new(id, 34..35, "unused")),
+            )
+            .unwrap();
+
+            String::from_utf8(term.into_inner().into_inner()).unwrap()
+        };
+
+        let output = render(Term::with_writer(NoColor::new(vec![])));
+
+        assert!(!output.contains("fn main() {"));
+
+        let output = render(Term::with_writer(NoColor::new(vec![])).with_context(5));
+
+        let golden = [
+            " warn: unused variable",
+            " --> main.rs:4:9",
+            "  ┌─ main.rs",
+            "1 │  use std::io;",
+            "2 │  ",
+            "3 │  fn main() {",
+            "4 │      let x = 1;",
+            "  │          ^ unused",
+            "5 │      println!();",
+            "6 │  }",
+            "",
+        ];
+
+        assert_eq!(output, golden.join("\n"));
+    }
+
+    #[test]
+    fn test_elision() {
+        let mut files = SourceCodes::default();
+
+        let source = (1..=80)
+            .map(|line| format!("line {line}\n"))
+            .collect::<String>();
+
+        let id = files.add("long.txt", source.clone());
+
+        let start = source.find("line 5\n").unwrap();
+        let end = source.find("line 80\n").unwrap();
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("mismatched lines").with_label(
+                Label::new(id, start..start + 6, "first").with_secondary(end..end + 7, "last"),
+            ),
+        );
+
+        assert_eq!(output.matches("┆").count(), 1);
+        assert!(output.contains(" 5 │  line 5\n"));
+        assert!(output.contains("   ┆\n80 │  line 80\n"));
+    }
+
+    #[test]
+    fn test_interior_lines() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add(
+            "main.rs",
+            "fn main() {\n    match x {\n        0 => a,\n        _ => b,\n    }\n}\n",
+        );
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("non-exhaustive patterns").with_label(Label::new(
+                id,
+                16..63,
+                "in this match",
+            )),
+        );
+
+        let golden = [
+            "error: non-exhaustive patterns",
+            " --> main.rs:2:5",
+            "  ┌─ main.rs",
+            "2 │        match x {",
+            "  │ _______^",
+            "3 │ │          0 => a,",
+            "4 │ │          _ => b,",
+            "5 │ │      }",
+            "  │ │______^ in this match",
+            "",
+        ];
+
+        assert_eq!(output, golden.join("\n"));
+    }
+
+    #[test]
+    fn test_inline_secondaries() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("call.rs", "fn main() {\nlet x = foo(bar, baz);\n}\n");
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("mismatched arguments").with_label(
+                Label::new(id, 20..32, "in this call")
+                    .with_secondary(24..27, "expected `u8`")
+                    .with_secondary(24..32, "found 2 arguments"),
+            ),
+        );
+
+        let golden = [
+            "error: mismatched arguments",
+            " --> call.rs:2:9",
+            "  ┌─ call.rs",
+            "2 │  let x = foo(bar, baz);",
+            "  │          ^^^^^^^^^^^^ in this call",
+            "  │              --- expected `u8`",
+            "  │              -------- found 2 arguments",
+            "",
+        ];
+
+        assert_eq!(output, golden.join("\n"));
+    }
+
+    #[test]
+    fn test_multibyte_boundary() {
+        let mut files = SourceCodes::default();
+
+        let id = files.add("fizz.txt", "fizz₁ : Nat → String\n");
+
+        let output = render_to_string(
+            &files,
+            Diagnostic::error("unknown name").with_label(Label::new(id, 0..7, "not found")),
+        );
+
+        assert!(output.contains("1 │  fizz₁ : Nat → String\n  │  ^^^^^ not found\n"));
+
+        let mut term = Term::with_writer(NoColor::new(vec![]));
+
+        let error = term
+            .render(
+                &files,
+                Diagnostic::error("unknown name").with_label(Label::new(id, 0..5, "not found")),
+            )
+            .unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(
+            error.to_string(),
+            "offset 5 is not on a char boundary in file id 0"
+        );
     }
 }
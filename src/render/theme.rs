@@ -0,0 +1,194 @@
+use std::borrow::Cow;
+
+use termcolor::{Color, ColorSpec};
+
+use crate::Level;
+
+/// Glyphs that underline the regions of a label.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Underline {
+    /// Glyph of the primary region.
+    pub primary: char,
+    /// Glyph of the secondary regions.
+    pub secondary: char,
+}
+
+impl Default for Underline {
+    fn default() -> Self {
+        Self {
+            primary: '^',
+            secondary: '-',
+        }
+    }
+}
+
+/// Visual style of the [`Term`](crate::term::Term) renderer.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// Underline glyphs of [`Bug`](Level::Bug) diagnostics.
+    pub bug_underline: Underline,
+    /// Underline glyphs of [`Error`](Level::Error) diagnostics.
+    pub error_underline: Underline,
+    /// Underline glyphs of [`Warning`](Level::Warning) diagnostics.
+    pub warning_underline: Underline,
+    /// Underline glyphs of [`Note`](Level::Note) diagnostics.
+    pub note_underline: Underline,
+    /// Underline glyphs of [`Help`](Level::Help) diagnostics.
+    pub help_underline: Underline,
+    /// Separator between the level/code and the message of the header, `": "` by default.
+    pub header_separator: Cow<'static, str>,
+    /// Color of the header message.
+    pub text_color: ColorSpec,
+    /// Color of the source lines and notes.
+    pub code_color: ColorSpec,
+    /// Color of the gutter, the file names and the secondary underlines.
+    pub label_color: ColorSpec,
+    /// Color of the primary underlines of [`Bug`](Level::Bug) and [`Error`](Level::Error) diagnostics.
+    pub primary_color: ColorSpec,
+    /// Color of the suggested replacements and the gutter marks of the lines they touch.
+    pub suggestion_color: ColorSpec,
+    /// Color of the level of [`Bug`](Level::Bug) diagnostics.
+    pub bug_color: Color,
+    /// Color of the level of [`Error`](Level::Error) diagnostics.
+    pub error_color: Color,
+    /// Color of the level and underlines of [`Warning`](Level::Warning) diagnostics.
+    pub warning_color: Color,
+    /// Color of the level and underlines of [`Note`](Level::Note) diagnostics.
+    pub note_color: Color,
+    /// Color of the level and underlines of [`Help`](Level::Help) diagnostics.
+    pub help_color: Color,
+}
+
+/// The default palette, for dark terminal backgrounds.
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            bug_underline: Underline::default(),
+            error_underline: Underline::default(),
+            warning_underline: Underline::default(),
+            note_underline: Underline::default(),
+            help_underline: Underline::default(),
+            header_separator: ": ".into(),
+            text_color: ColorSpec::new()
+                .set_bold(true)
+                .set_fg(Some(Color::White))
+                .clone(),
+            code_color: ColorSpec::new().set_fg(Some(Color::White)).clone(),
+            label_color: ColorSpec::new().set_fg(Some(Color::Blue)).clone(),
+            primary_color: ColorSpec::new().set_fg(Some(Color::Red)).clone(),
+            suggestion_color: ColorSpec::new()
+                .set_bold(true)
+                .set_fg(Some(Color::Green))
+                .clone(),
+            bug_color: Level::Bug.default_color(),
+            error_color: Level::Error.default_color(),
+            warning_color: Level::Warning.default_color(),
+            note_color: Level::Note.default_color(),
+            help_color: Level::Help.default_color(),
+        }
+    }
+}
+
+impl Theme {
+    /// Returns a palette for light terminal backgrounds, where white text is unreadable.
+    pub fn light() -> Self {
+        Self {
+            text_color: ColorSpec::new()
+                .set_bold(true)
+                .set_fg(Some(Color::Black))
+                .clone(),
+            code_color: ColorSpec::new().set_fg(Some(Color::Black)).clone(),
+            note_color: Color::Black,
+            help_color: Color::Ansi256(240),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the [`light`](Self::light) palette if the `COLORFGBG` environment variable
+    /// reports a light terminal background, or the default dark palette otherwise.
+    pub fn auto() -> Self {
+        if is_light_background(std::env::var("COLORFGBG").ok().as_deref()) {
+            Self::light()
+        } else {
+            Self::default()
+        }
+    }
+
+    /// Returns the header color of diagnostics with severity `level`.
+    pub fn level_color(&self, level: Level) -> Color {
+        match level {
+            Level::Bug => self.bug_color,
+            Level::Error => self.error_color,
+            Level::Warning => self.warning_color,
+            Level::Note => self.note_color,
+            Level::Help => self.help_color,
+        }
+    }
+
+    /// Returns the color of the primary underlines of diagnostics with severity `level`.
+    pub fn underline_color(&self, level: Level) -> ColorSpec {
+        match level {
+            Level::Bug | Level::Error => self.primary_color.clone(),
+            level => ColorSpec::new()
+                .set_fg(Some(self.level_color(level)))
+                .clone(),
+        }
+    }
+
+    /// Returns the underline glyphs of diagnostics with severity `level`.
+    pub fn underline(&self, level: Level) -> Underline {
+        match level {
+            Level::Bug => self.bug_underline,
+            Level::Error => self.error_underline,
+            Level::Warning => self.warning_underline,
+            Level::Note => self.note_underline,
+            Level::Help => self.help_underline,
+        }
+    }
+}
+
+/// Returns true if `colorfgbg`, formatted as `fg;bg` or `fg;default;bg`, has a light background.
+///
+/// Background 7 (white) and 9-15 (bright colors but 8) are light, other or missing values are dark.
+fn is_light_background(colorfgbg: Option<&str>) -> bool {
+    colorfgbg
+        .and_then(|value| value.rsplit(';').next())
+        .and_then(|bg| bg.trim().parse::<u8>().ok())
+        .is_some_and(|bg| bg == 7 || (9..=15).contains(&bg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_light_background() {
+        assert!(is_light_background(Some("0;15")));
+        assert!(is_light_background(Some("0;default;7")));
+        assert!(!is_light_background(Some("15;0")));
+        assert!(!is_light_background(Some("15;8")));
+        assert!(!is_light_background(Some("garbage")));
+        assert!(!is_light_background(None));
+
+        assert_eq!(Theme::light().level_color(Level::Note), Color::Black);
+        assert_eq!(Theme::default().level_color(Level::Note), Color::White);
+        assert_eq!(Theme::light().level_color(Level::Error), Color::Red);
+    }
+
+    #[test]
+    fn test_underline_color() {
+        let theme = Theme {
+            primary_color: ColorSpec::new().set_fg(Some(Color::Cyan)).clone(),
+            warning_color: Color::Green,
+            ..Default::default()
+        };
+
+        assert_eq!(theme.underline_color(Level::Bug).fg(), Some(&Color::Cyan));
+        assert_eq!(theme.underline_color(Level::Error).fg(), Some(&Color::Cyan));
+        assert_eq!(
+            theme.underline_color(Level::Warning).fg(),
+            Some(&Color::Green)
+        );
+        assert_eq!(theme.level_color(Level::Bug), Color::Magenta);
+    }
+}
@@ -0,0 +1,191 @@
+//! A renderer implementation that produces styled [`ratatui`] text.
+
+use std::io::{Result, Write};
+
+use ratatui::{
+    style::{Color as TuiColor, Modifier, Style},
+    text::{Line, Span, Text},
+};
+use termcolor::{Color, ColorSpec, WriteColor};
+
+use crate::Diagnostic;
+
+use super::{Files, Renderer, term::Term};
+
+/// A [`WriteColor`] that collects the output of [`Term`] as styled lines.
+#[derive(Default)]
+struct TextWriter {
+    lines: Vec<Line<'static>>,
+    spans: Vec<Span<'static>>,
+    pending: Vec<u8>,
+    style: Style,
+}
+
+impl TextWriter {
+    fn push_span(&mut self) {
+        if !self.pending.is_empty() {
+            let content = String::from_utf8_lossy(&self.pending).into_owned();
+            self.spans.push(Span::styled(content, self.style));
+            self.pending.clear();
+        }
+    }
+
+    fn push_line(&mut self) {
+        self.push_span();
+        self.lines.push(Line::from(std::mem::take(&mut self.spans)));
+    }
+
+    fn into_text(mut self) -> Text<'static> {
+        if !self.pending.is_empty() || !self.spans.is_empty() {
+            self.push_line();
+        }
+
+        Text::from(self.lines)
+    }
+}
+
+impl Write for TextWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        for byte in buf {
+            if *byte == b'\n' {
+                self.push_line();
+            } else {
+                self.pending.push(*byte);
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl WriteColor for TextWriter {
+    fn supports_color(&self) -> bool {
+        true
+    }
+
+    fn set_color(&mut self, spec: &ColorSpec) -> Result<()> {
+        self.push_span();
+        self.style = to_style(spec);
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.push_span();
+        self.style = Style::default();
+        Ok(())
+    }
+}
+
+fn to_color(color: &Color) -> TuiColor {
+    match color {
+        Color::Black => TuiColor::Black,
+        Color::Blue => TuiColor::Blue,
+        Color::Green => TuiColor::Green,
+        Color::Red => TuiColor::Red,
+        Color::Cyan => TuiColor::Cyan,
+        Color::Magenta => TuiColor::Magenta,
+        Color::Yellow => TuiColor::Yellow,
+        Color::White => TuiColor::White,
+        Color::Ansi256(index) => TuiColor::Indexed(*index),
+        Color::Rgb(r, g, b) => TuiColor::Rgb(*r, *g, *b),
+        _ => TuiColor::Reset,
+    }
+}
+
+fn to_style(spec: &ColorSpec) -> Style {
+    let mut style = Style::default();
+
+    if let Some(color) = spec.fg() {
+        style = style.fg(to_color(color));
+    }
+
+    if let Some(color) = spec.bg() {
+        style = style.bg(to_color(color));
+    }
+
+    if spec.bold() {
+        style = style.add_modifier(Modifier::BOLD);
+    }
+
+    if spec.italic() {
+        style = style.add_modifier(Modifier::ITALIC);
+    }
+
+    if spec.underline() {
+        style = style.add_modifier(Modifier::UNDERLINED);
+    }
+
+    style
+}
+
+/// A diagnostic reporting renderer that produces a styled [`Text`], so that diagnostics can be
+/// shown inside a `ratatui` widget.
+///
+/// The layout is the same as [`Term`], and every color maps to the matching [`TuiColor`].
+pub struct Tui(Term<TextWriter>);
+
+impl Default for Tui {
+    fn default() -> Self {
+        Self(Term::with_writer(TextWriter::default()))
+    }
+}
+
+impl Tui {
+    /// Consume this `Tui`, returning the text of all rendered diagnostics.
+    pub fn into_text(self) -> Text<'static> {
+        self.0.into_inner().into_text()
+    }
+}
+
+impl Renderer for Tui {
+    type Error = std::io::Error;
+
+    fn render<'a, F, D>(&mut self, files: &F, diagnostic: D) -> Result<()>
+    where
+        F: Files,
+        Diagnostic<'a>: From<D>,
+    {
+        self.0.render(files, diagnostic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Color;
+
+    use crate::{Diagnostic, Label, Renderer, SourceCodes};
+
+    use super::Tui;
+
+    #[test]
+    fn test_tui() {
+        let mut files = SourceCodes::default();
+
+        files.add("src/main.rs", "fn main() {\n    let x = 1\n}\n");
+
+        let diagnostic = || {
+            Diagnostic::error("expected `;`")
+                .with_label(Label::new(0, 16..25, "missing `;`"))
+                .with_note("statements end with `;`")
+        };
+
+        let mut renderer = Tui::default();
+
+        renderer.render(&files, diagnostic()).unwrap();
+
+        let text = renderer.into_text();
+
+        let plain = diagnostic().to_plain_string(&files).unwrap();
+
+        assert_eq!(text.lines.len(), plain.lines().count());
+
+        let level = &text.lines[0].spans[0];
+
+        assert_eq!(level.content, "error");
+        assert_eq!(level.style.fg, Some(Color::Red));
+    }
+}